@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,9 +14,202 @@ pub struct Config {
     #[serde(default)]
     pub disable_ai: bool,
 
+    /// Backend which translates messages
+    #[serde(default)]
+    pub translation_backend: TranslationBackendConfig,
+
     /// Default language for translations
     #[serde(default = "default_target_language")]
     pub target_language: String,
+
+    /// Languages to translate your messages into before sending them, for peers who can't run
+    /// a translation model themselves
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outgoing_translations: Vec<String>,
+
+    /// Number of earlier messages of a room the model sees while translating a message, to
+    /// get pronouns and short replies right. 0 translates each message on its own
+    #[serde(default = "default_translation_context")]
+    pub translation_context: usize,
+
+    /// Directory for persistent data such as chat history. Defaults to ~/.local/share/puf
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Path to the private key file of your peer identity. Defaults to ~/.config/puf/identity.key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<PathBuf>,
+
+    /// Discover peers on the local network via mDNS
+    #[serde(default = "default_local_discovery")]
+    pub local_discovery: bool,
+
+    /// Peers to connect to directly on startup, to reach people outside the local network
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bootstrap_peers: Vec<BootstrapPeer>,
+
+    /// Relay server used to reach peers behind NATs and firewalls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_url: Option<String>,
+
+    /// Act as an entry node which others list in their bootstrap peers. Such a node joins the
+    /// network without knowing any other peer first
+    #[serde(default)]
+    pub bootstrap: bool,
+
+    /// Passphrase of a private peer network. Peers using a different or no passphrase never
+    /// meet, so a team can run its own isolated network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_passphrase: Option<String>,
+}
+
+/// How messages are translated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TranslationBackendConfig {
+    /// Local kalosm Llama model, downloaded on first use
+    Llama(LlamaConfig),
+    /// OpenAI-compatible chat completions endpoint, such as a llama.cpp or Ollama server shared
+    /// on the local network
+    #[serde(rename = "openai")]
+    OpenAi {
+        /// Base URL of the API, e.g. http://gpu-box:8080/v1
+        url: String,
+        /// Model name passed to the server
+        model: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key: Option<String>,
+    },
+    /// Translations looked up in a JSON file mapping languages to phrases and their
+    /// translations. Texts without an entry, or all texts without a file, fail to translate
+    Dictionary {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+    },
+}
+
+impl Default for TranslationBackendConfig {
+    fn default() -> Self {
+        Self::Llama(LlamaConfig::default())
+    }
+}
+
+impl TranslationBackendConfig {
+    /// Identifies the model translations come from, so cached translations of another model
+    /// aren't reused. `None` for the dictionary, whose lookups aren't worth caching.
+    pub fn model_id(&self) -> Option<String> {
+        match self {
+            Self::Llama(llama_config) => {
+                let model = serde_json::to_string(&llama_config.model).ok()?;
+                Some(format!("llama:{}", model))
+            }
+            Self::OpenAi { url, model, .. } => Some(format!("openai:{}:{}", url, model)),
+            Self::Dictionary { .. } => None,
+        }
+    }
+}
+
+/// Settings of the local Llama backend
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LlamaConfig {
+    /// Model to run, a preset or a GGUF file on disk or on Hugging Face
+    #[serde(default)]
+    pub model: ModelSource,
+
+    /// Maximum number of tokens of a prompt and its translation together. Longer messages
+    /// aren't translated. This doesn't change the context length of the model, which is set
+    /// by the model file. Unlimited by default
+    #[serde(
+        default,
+        alias = "context_size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_tokens: Option<u32>,
+
+    /// Number of CPU threads used for inference. Defaults to one per core
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<usize>,
+
+    #[serde(default)]
+    pub sampler: SamplerConfig,
+}
+
+/// Where the model of the local Llama backend comes from. The quantization is chosen by
+/// picking a GGUF file, e.g. a Q4_K_M file for a smaller and faster model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModelSource {
+    /// One of the models kalosm knows, e.g. "llama-3.2-1b-chat"
+    Preset(LlamaModel),
+    /// GGUF file on disk, which is used without network access
+    File {
+        path: PathBuf,
+        /// Hex encoded SHA-256 checksum the file is verified against before loading
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// GGUF file in a Hugging Face repository
+    HuggingFace {
+        repo: String,
+        file: String,
+        #[serde(default = "default_revision")]
+        revision: String,
+    },
+}
+
+impl Default for ModelSource {
+    fn default() -> Self {
+        Self::Preset(LlamaModel::default())
+    }
+}
+
+/// Sampling settings of the local Llama backend. Unset values keep kalosm's defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplerConfig {
+    /// Randomness of the output, lower values give more literal translations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Penalty for repeating tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repetition_penalty: Option<f32>,
+
+    /// Seed for reproducible output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+/// Llama models available to the local backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LlamaModel {
+    #[default]
+    #[serde(rename = "llama-3.1-8b-chat")]
+    Llama3_1_8bChat,
+    #[serde(rename = "llama-3.2-3b-chat")]
+    Llama3_2_3bChat,
+    #[serde(rename = "llama-3.2-1b-chat")]
+    Llama3_2_1bChat,
+    #[serde(rename = "phi-3.5-mini")]
+    Phi3_5Mini,
+    #[serde(rename = "qwen-2.5-3b-instruct")]
+    Qwen2_5_3bInstruct,
+    #[serde(rename = "qwen-2.5-1.5b-instruct")]
+    Qwen2_5_1_5bInstruct,
+}
+
+/// A known peer to connect to without discovering it first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BootstrapPeer {
+    /// Hex encoded public key of the peer, as shown by `identity show`
+    pub node_id: String,
+
+    /// Addresses the peer can be reached at directly
+    #[serde(default)]
+    pub addresses: Vec<SocketAddr>,
+
+    /// Relay server the peer is connected to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_url: Option<String>,
 }
 
 fn default_username() -> String {
@@ -26,12 +220,34 @@ fn default_target_language() -> String {
     "Spanish".to_string()
 }
 
+fn default_translation_context() -> usize {
+    3
+}
+
+fn default_revision() -> String {
+    "main".to_string()
+}
+
+fn default_local_discovery() -> bool {
+    true
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             username: default_username(),
             disable_ai: false,
+            translation_backend: TranslationBackendConfig::default(),
             target_language: default_target_language(),
+            outgoing_translations: Vec::new(),
+            translation_context: default_translation_context(),
+            data_dir: None,
+            identity_file: None,
+            local_discovery: default_local_discovery(),
+            bootstrap_peers: Vec::new(),
+            relay_url: None,
+            bootstrap: false,
+            network_passphrase: None,
         }
     }
 }
@@ -44,6 +260,28 @@ impl Config {
         Ok(home_dir.join(".config").join("puf").join("config.toml"))
     }
 
+    /// Get the data directory, falling back to ~/.local/share/puf
+    pub fn data_dir(&self) -> Result<PathBuf> {
+        if let Some(data_dir) = &self.data_dir {
+            return Ok(data_dir.clone());
+        }
+
+        let home_dir = std::env::home_dir().context("Could not determine home directory")?;
+
+        Ok(home_dir.join(".local").join("share").join("puf"))
+    }
+
+    /// Get the identity key file path, falling back to ~/.config/puf/identity.key
+    pub fn identity_path(&self) -> Result<PathBuf> {
+        if let Some(identity_file) = &self.identity_file {
+            return Ok(identity_file.clone());
+        }
+
+        let home_dir = std::env::home_dir().context("Could not determine home directory")?;
+
+        Ok(home_dir.join(".config").join("puf").join("identity.key"))
+    }
+
     /// Load config from a file path, creating default config if file doesn't exist
     pub fn load_from_path(path: &Path) -> Result<Self> {
         if path.exists() {
@@ -74,6 +312,21 @@ impl Config {
         Self::load_from_path(&config_path)
     }
 
+    /// Run the local Llama backend with a model file, keeping its other settings if the local
+    /// backend is already configured.
+    pub fn use_model_file(&mut self, path: PathBuf, sha256: Option<String>) {
+        let model = ModelSource::File { path, sha256 };
+        match &mut self.translation_backend {
+            TranslationBackendConfig::Llama(llama_config) => llama_config.model = model,
+            backend => {
+                *backend = TranslationBackendConfig::Llama(LlamaConfig {
+                    model,
+                    ..LlamaConfig::default()
+                })
+            }
+        }
+    }
+
     /// Save config to a file path
     pub fn save_to_path(&self, path: &Path) -> Result<()> {
         // Create parent directories if they don't exist
@@ -105,6 +358,177 @@ mod tests {
         assert_eq!(config.username, "Anonymous");
         assert!(!config.disable_ai);
         assert_eq!(config.target_language, "Spanish");
+        assert_eq!(config.translation_context, 3);
+        assert!(config.data_dir.is_none());
+        assert!(config.local_discovery);
+        assert!(config.bootstrap_peers.is_empty());
+        assert!(config.network_passphrase.is_none());
+        assert_eq!(
+            config.translation_backend,
+            TranslationBackendConfig::Llama(LlamaConfig {
+                model: ModelSource::Preset(LlamaModel::Llama3_1_8bChat),
+                max_tokens: None,
+                threads: None,
+                sampler: SamplerConfig::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_translation_backend_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            [translation_backend]
+            type = "openai"
+            url = "http://gpu-box:8080/v1"
+            model = "llama3.2"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.translation_backend,
+            TranslationBackendConfig::OpenAi {
+                url: "http://gpu-box:8080/v1".to_string(),
+                model: "llama3.2".to_string(),
+                api_key: None,
+            }
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+            [translation_backend]
+            type = "llama"
+            model = "qwen-2.5-3b-instruct"
+            threads = 4
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.translation_backend,
+            TranslationBackendConfig::Llama(LlamaConfig {
+                model: ModelSource::Preset(LlamaModel::Qwen2_5_3bInstruct),
+                threads: Some(4),
+                ..LlamaConfig::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_model_sources_from_toml() {
+        let llama_config =
+            |toml: &str| match toml::from_str::<Config>(toml).unwrap().translation_backend {
+                TranslationBackendConfig::Llama(llama_config) => llama_config,
+                backend => panic!("Unexpected backend: {backend:?}"),
+            };
+
+        let config = llama_config(
+            r#"
+            [translation_backend]
+            type = "llama"
+            model = { path = "/models/llama.Q4_K_M.gguf" }
+            max_tokens = 2048
+            "#,
+        );
+        assert_eq!(
+            config.model,
+            ModelSource::File {
+                path: PathBuf::from("/models/llama.Q4_K_M.gguf"),
+                sha256: None,
+            }
+        );
+        assert_eq!(config.max_tokens, Some(2048));
+
+        let config = llama_config(
+            r#"
+            [translation_backend]
+            type = "llama"
+            model = { repo = "bartowski/Llama-3.2-1B-Instruct-GGUF", file = "Llama-3.2-1B-Instruct-Q4_K_M.gguf" }
+
+            [translation_backend.sampler]
+            temperature = 0.2
+            "#,
+        );
+        assert_eq!(
+            config.model,
+            ModelSource::HuggingFace {
+                repo: "bartowski/Llama-3.2-1B-Instruct-GGUF".to_string(),
+                file: "Llama-3.2-1B-Instruct-Q4_K_M.gguf".to_string(),
+                revision: "main".to_string(),
+            }
+        );
+        assert_eq!(config.sampler.temperature, Some(0.2));
+    }
+
+    #[test]
+    fn test_bootstrap_peers_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            local_discovery = false
+            relay_url = "https://relay.example.com"
+
+            [[bootstrap_peers]]
+            node_id = "abcd"
+            addresses = ["203.0.113.7:2022", "[2001:db8::1]:2023"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(!config.local_discovery);
+        assert_eq!(
+            config.relay_url.as_deref(),
+            Some("https://relay.example.com")
+        );
+        assert_eq!(config.bootstrap_peers.len(), 1);
+        assert_eq!(config.bootstrap_peers[0].addresses.len(), 2);
+        assert!(config.bootstrap_peers[0].relay_url.is_none());
+    }
+
+    #[test]
+    fn test_use_model_file() {
+        let mut config = Config {
+            translation_backend: TranslationBackendConfig::Llama(LlamaConfig {
+                threads: Some(2),
+                ..LlamaConfig::default()
+            }),
+            ..Config::default()
+        };
+        config.use_model_file(PathBuf::from("/models/a.gguf"), Some("ab".repeat(32)));
+        assert_eq!(
+            config.translation_backend,
+            TranslationBackendConfig::Llama(LlamaConfig {
+                model: ModelSource::File {
+                    path: PathBuf::from("/models/a.gguf"),
+                    sha256: Some("ab".repeat(32)),
+                },
+                threads: Some(2),
+                ..LlamaConfig::default()
+            })
+        );
+
+        // Other backends are replaced by the local one
+        let mut config = Config {
+            translation_backend: TranslationBackendConfig::Dictionary { path: None },
+            ..Config::default()
+        };
+        config.use_model_file(PathBuf::from("/models/a.gguf"), None);
+        assert!(matches!(
+            config.translation_backend,
+            TranslationBackendConfig::Llama(LlamaConfig {
+                model: ModelSource::File { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_data_dir_override() -> Result<()> {
+        let config = Config {
+            data_dir: Some(PathBuf::from("/tmp/puf-data")),
+            ..Config::default()
+        };
+        assert_eq!(config.data_dir()?, PathBuf::from("/tmp/puf-data"));
+
+        Ok(())
     }
 
     #[test]
@@ -112,7 +536,34 @@ mod tests {
         let config = Config {
             username: "TestUser".to_string(),
             disable_ai: true,
+            translation_backend: TranslationBackendConfig::Llama(LlamaConfig {
+                model: ModelSource::HuggingFace {
+                    repo: "acme/model-GGUF".to_string(),
+                    file: "model.Q4_K_M.gguf".to_string(),
+                    revision: "main".to_string(),
+                },
+                max_tokens: Some(2048),
+                threads: Some(4),
+                sampler: SamplerConfig {
+                    temperature: Some(0.2),
+                    repetition_penalty: None,
+                    seed: Some(7),
+                },
+            }),
             target_language: "French".to_string(),
+            outgoing_translations: vec!["German".to_string()],
+            translation_context: 5,
+            data_dir: Some(PathBuf::from("/tmp/puf")),
+            identity_file: Some(PathBuf::from("/tmp/puf/identity.key")),
+            local_discovery: false,
+            bootstrap_peers: vec![BootstrapPeer {
+                node_id: "ab".repeat(32),
+                addresses: vec!["203.0.113.7:2022".parse().unwrap()],
+                relay_url: None,
+            }],
+            relay_url: Some("https://relay.example.com".to_string()),
+            bootstrap: true,
+            network_passphrase: Some("acme".to_string()),
         };
 
         let toml_str = toml::to_string(&config).unwrap();
@@ -120,7 +571,20 @@ mod tests {
 
         assert_eq!(config.username, deserialized.username);
         assert_eq!(config.disable_ai, deserialized.disable_ai);
+        assert_eq!(config.translation_backend, deserialized.translation_backend);
         assert_eq!(config.target_language, deserialized.target_language);
+        assert_eq!(
+            config.outgoing_translations,
+            deserialized.outgoing_translations
+        );
+        assert_eq!(config.translation_context, deserialized.translation_context);
+        assert_eq!(config.data_dir, deserialized.data_dir);
+        assert_eq!(config.identity_file, deserialized.identity_file);
+        assert_eq!(config.local_discovery, deserialized.local_discovery);
+        assert_eq!(config.bootstrap_peers, deserialized.bootstrap_peers);
+        assert_eq!(config.relay_url, deserialized.relay_url);
+        assert_eq!(config.bootstrap, deserialized.bootstrap);
+        assert_eq!(config.network_passphrase, deserialized.network_passphrase);
     }
 
     #[test]
@@ -131,7 +595,17 @@ mod tests {
         let original_config = Config {
             username: "TestUser".to_string(),
             disable_ai: true,
+            translation_backend: TranslationBackendConfig::default(),
             target_language: "German".to_string(),
+            outgoing_translations: Vec::new(),
+            translation_context: 0,
+            data_dir: None,
+            identity_file: None,
+            local_discovery: true,
+            bootstrap_peers: Vec::new(),
+            relay_url: None,
+            bootstrap: false,
+            network_passphrase: None,
         };
 
         // Save config
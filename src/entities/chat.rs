@@ -1,8 +1,25 @@
+use p2panda_core::PublicKey;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::SystemTime;
 
+use crate::identity::fingerprint;
+use crate::language::{detect_language, is_same_language};
+
 static NEXT_ID: AtomicU64 = AtomicU64::new(0);
 
+/// Where the translation of a message stands for its reader
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranslationState {
+    /// Waiting for or being generated
+    Pending,
+    Done,
+    /// Translating failed, with the reason
+    Failed(String),
+    /// Not translated, because the message needs no translation or it can't be translated
+    /// locally
+    Skipped,
+}
+
 #[derive(Debug, Clone)]
 pub struct Message {
     pub id: u64,
@@ -10,12 +27,20 @@ pub struct Message {
     pub timestamp: SystemTime,
     pub translation: Option<String>,
     pub translation_language: Option<String>,
+    /// Translation while it is still being generated
+    pub partial_translation: Option<String>,
+    /// Why the last translation attempt failed
+    pub translation_error: Option<String>,
+    /// Language the message is written in, if it could be detected
+    pub source_language: Option<String>,
     pub sender: String,
+    pub author: Option<PublicKey>,
 }
 
 impl Message {
     pub fn new(content: String, sender: String) -> Self {
         let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let source_language = detect_language(&content);
 
         Self {
             id,
@@ -23,24 +48,106 @@ impl Message {
             timestamp: SystemTime::now(),
             translation: None,
             translation_language: None,
+            partial_translation: None,
+            translation_error: None,
+            source_language,
             sender,
+            author: None,
         }
     }
 
+    pub fn with_author(mut self, author: Option<PublicKey>) -> Self {
+        self.author = author;
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
     pub fn with_translation(mut self, translation: String, language: String) -> Self {
         self.translation = Some(translation);
         self.translation_language = Some(language);
         self
     }
 
+    /// Display name of the sender, followed by their key fingerprint when known
+    pub fn sender_label(&self) -> String {
+        match &self.author {
+            Some(author) => format!("{} [{}]", self.sender, fingerprint(author)),
+            None => self.sender.clone(),
+        }
+    }
+
+    /// Whether `reader` has to have the message translated into `target_language`. Their own
+    /// messages and messages already in the target language are shown as written.
+    pub fn needs_translation(&self, target_language: &str, reader: Option<PublicKey>) -> bool {
+        if reader.is_some() && self.author == reader {
+            return false;
+        }
+
+        match &self.source_language {
+            Some(source_language) => !is_same_language(source_language, target_language),
+            None => true,
+        }
+    }
+
+    /// Where the translation of the message into `target_language` stands for `reader`.
+    /// Without `translating_locally` only translations attached by the sender can show up.
+    pub fn translation_state(
+        &self,
+        target_language: &str,
+        reader: Option<PublicKey>,
+        translating_locally: bool,
+    ) -> TranslationState {
+        if self.translation.is_some() {
+            TranslationState::Done
+        } else if !translating_locally || !self.needs_translation(target_language, reader) {
+            TranslationState::Skipped
+        } else if let Some(error) = &self.translation_error {
+            TranslationState::Failed(error.clone())
+        } else {
+            TranslationState::Pending
+        }
+    }
+
     pub fn display_original(&self) -> String {
-        format!("{}: {}", self.sender, self.content)
+        match &self.source_language {
+            Some(language) => format!("{} ({}): {}", self.sender_label(), language, self.content),
+            None => format!("{}: {}", self.sender_label(), self.content),
+        }
     }
 
-    pub fn display_translation(&self) -> String {
-        match &self.translation {
-            Some(trans) => format!("{}: {}", self.sender, trans),
-            None => format!("{}: Translating...", self.sender),
+    /// Translation line for the message. Without `translating_locally` only translations
+    /// attached by the sender can show up.
+    pub fn display_translation(
+        &self,
+        target_language: &str,
+        reader: Option<PublicKey>,
+        translating_locally: bool,
+    ) -> String {
+        match self.translation_state(target_language, reader, translating_locally) {
+            TranslationState::Done => format!(
+                "{}: {}",
+                self.sender_label(),
+                self.translation.as_deref().unwrap_or_default()
+            ),
+            TranslationState::Skipped if !self.needs_translation(target_language, reader) => {
+                format!("{}: {}", self.sender_label(), self.content)
+            }
+            TranslationState::Skipped => {
+                format!("{}: {} (untranslated)", self.sender_label(), self.content)
+            }
+            TranslationState::Failed(error) => {
+                format!("{}: Translation failed: {}", self.sender_label(), error)
+            }
+            TranslationState::Pending if self.partial_translation.is_some() => format!(
+                "{}: {}...",
+                self.sender_label(),
+                self.partial_translation.as_deref().unwrap_or_default()
+            ),
+            TranslationState::Pending => format!("{}: Translating...", self.sender_label()),
         }
     }
 }
@@ -65,18 +172,70 @@ impl Chat {
         Self::default()
     }
 
-    pub fn add_message(&mut self, content: String, sender: String) -> anyhow::Result<&Message> {
-        let message = Message::new(content, sender);
+    pub fn add_message(
+        &mut self,
+        content: String,
+        sender: String,
+        author: Option<PublicKey>,
+    ) -> anyhow::Result<&Message> {
+        let message = Message::new(content, sender).with_author(author);
         self.messages.push(message);
         self.messages
             .last()
             .ok_or(anyhow::anyhow!("No message found"))
     }
 
+    /// Up to `count` messages before the given one, oldest first, as "sender: content" lines
+    pub fn context_before(&self, message_id: u64, count: usize) -> Vec<String> {
+        let Some(index) = self.messages.iter().position(|m| m.id == message_id) else {
+            return Vec::new();
+        };
+
+        self.messages[index.saturating_sub(count)..index]
+            .iter()
+            .map(|msg| format!("{}: {}", msg.sender, msg.content))
+            .collect()
+    }
+
     pub fn update_translation(&mut self, message_id: u64, translation: String) {
         if let Some(msg) = self.messages.iter_mut().find(|m| m.id == message_id) {
             msg.translation = Some(translation);
             msg.translation_language = Some(self.target_language.clone());
+            msg.partial_translation = None;
+            msg.translation_error = None;
+        }
+    }
+
+    /// Record that translating a message failed, dropping its partial translation
+    pub fn fail_translation(&mut self, message_id: u64, error: String) {
+        if let Some(msg) = self
+            .messages
+            .iter_mut()
+            .find(|m| m.id == message_id && m.translation.is_none())
+        {
+            msg.partial_translation = None;
+            msg.translation_error = Some(error);
+        }
+    }
+
+    /// Forget that translating a message failed, so it is translated again. Returns false if
+    /// it didn't fail.
+    pub fn retry_translation(&mut self, message_id: u64) -> bool {
+        self.messages
+            .iter_mut()
+            .find(|m| m.id == message_id)
+            .and_then(|msg| msg.translation_error.take())
+            .is_some()
+    }
+
+    /// Show the translation of a message so far, until the final translation arrives
+    pub fn update_partial_translation(&mut self, message_id: u64, partial_translation: String) {
+        if let Some(msg) = self
+            .messages
+            .iter_mut()
+            .find(|m| m.id == message_id && m.translation.is_none())
+        {
+            msg.partial_translation = Some(partial_translation);
         }
     }
 
@@ -86,6 +245,8 @@ impl Chat {
         for msg in &mut self.messages {
             msg.translation = None;
             msg.translation_language = None;
+            msg.partial_translation = None;
+            msg.translation_error = None;
         }
     }
 }
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result};
+use p2panda_core::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::entities::chat::Message;
+use crate::room_manager::Room;
+
+/// A single line in a room's history file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+// Records are short-lived while reading or writing a line, boxing would only complicate serde
+#[allow(clippy::large_enum_variant)]
+enum HistoryRecord {
+    /// A sent or received message
+    Message {
+        content: String,
+        timestamp: SystemTime,
+        sender: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<PublicKey>,
+    },
+    /// A translation for the message at `index` (counted in message records)
+    Translation {
+        index: usize,
+        translation: String,
+        language: String,
+    },
+}
+
+/// Append-only chat history for a single room, stored as JSON lines on disk.
+#[derive(Debug)]
+pub struct ChatHistory {
+    path: PathBuf,
+    /// Maps in-memory message IDs to their position in the history file
+    indices: HashMap<u64, usize>,
+    message_count: usize,
+}
+
+impl ChatHistory {
+    /// Open the history file for a room inside the given data directory.
+    pub fn open(data_dir: &Path, room: &Room) -> Result<Self> {
+        let rooms_dir = data_dir.join("rooms");
+        fs::create_dir_all(&rooms_dir).with_context(|| {
+            format!(
+                "Failed to create history directory: {}",
+                rooms_dir.display()
+            )
+        })?;
+
+        Ok(Self {
+            path: rooms_dir.join(format!("{}.jsonl", room.topic_hash())),
+            indices: HashMap::new(),
+            message_count: 0,
+        })
+    }
+
+    /// Load all stored messages, with their translations applied.
+    pub fn load(&mut self) -> Result<Vec<Message>> {
+        self.indices.clear();
+        self.message_count = 0;
+
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open history file: {}", self.path.display()))?;
+
+        let mut messages: Vec<Message> = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record = match serde_json::from_str::<HistoryRecord>(&line) {
+                Ok(record) => record,
+                Err(e) => {
+                    // A partially written last line should not lose the whole history
+                    tracing::warn!("Skipping malformed history record: {}", e);
+                    continue;
+                }
+            };
+
+            match record {
+                HistoryRecord::Message {
+                    content,
+                    timestamp,
+                    sender,
+                    author,
+                } => {
+                    let message = Message::new(content, sender)
+                        .with_timestamp(timestamp)
+                        .with_author(author);
+                    self.indices.insert(message.id, messages.len());
+                    messages.push(message);
+                }
+                HistoryRecord::Translation {
+                    index,
+                    translation,
+                    language,
+                } => {
+                    if let Some(message) = messages.get_mut(index) {
+                        message.translation = Some(translation);
+                        message.translation_language = Some(language);
+                    }
+                }
+            }
+        }
+
+        self.message_count = messages.len();
+        Ok(messages)
+    }
+
+    /// Append a sent or received message.
+    pub fn append_message(&mut self, message: &Message) -> Result<()> {
+        self.append(&HistoryRecord::Message {
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+            sender: message.sender.clone(),
+            author: message.author,
+        })?;
+
+        self.indices.insert(message.id, self.message_count);
+        self.message_count += 1;
+        Ok(())
+    }
+
+    /// Append a translation for a previously stored message. Unknown messages are ignored.
+    pub fn append_translation(
+        &mut self,
+        message_id: u64,
+        translation: &str,
+        language: &str,
+    ) -> Result<()> {
+        let Some(&index) = self.indices.get(&message_id) else {
+            return Ok(());
+        };
+
+        self.append(&HistoryRecord::Translation {
+            index,
+            translation: translation.to_string(),
+            language: language.to_string(),
+        })
+    }
+
+    fn append(&self, record: &HistoryRecord) -> Result<()> {
+        let mut line = serde_json::to_string(record).context("Failed to serialize history")?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open history file: {}", self.path.display()))?;
+
+        // Start on a new line after a partially written one, which `load` skips, so this
+        // record isn't lost with it
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.insert(0, '\n');
+            }
+        }
+
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("Failed to write history file: {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_history_round_trip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let room = Room::new("history-room".to_string());
+
+        let mut history = ChatHistory::open(temp_dir.path(), &room)?;
+        assert!(history.load()?.is_empty());
+
+        let first = Message::new("Hello".to_string(), "Alice".to_string());
+        let bob = p2panda_core::PrivateKey::new().public_key();
+        let second =
+            Message::new("How are you?".to_string(), "Bob".to_string()).with_author(Some(bob));
+        history.append_message(&first)?;
+        history.append_message(&second)?;
+        history.append_translation(second.id, "¿Cómo estás?", "Spanish")?;
+
+        let mut reopened = ChatHistory::open(temp_dir.path(), &room)?;
+        let messages = reopened.load()?;
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].content, "Hello");
+        assert_eq!(messages[0].sender, "Alice");
+        assert_eq!(messages[0].timestamp, first.timestamp);
+        assert!(messages[0].translation.is_none());
+        assert!(messages[0].author.is_none());
+        assert_eq!(messages[1].author, Some(bob));
+        assert_eq!(messages[1].translation.as_deref(), Some("¿Cómo estás?"));
+        assert_eq!(messages[1].translation_language.as_deref(), Some("Spanish"));
+
+        // Translations for reloaded messages are tracked under their new IDs
+        reopened.append_translation(messages[0].id, "Hola", "Spanish")?;
+        let messages = ChatHistory::open(temp_dir.path(), &room)?.load()?;
+        assert_eq!(messages[0].translation.as_deref(), Some("Hola"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_is_per_room() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let room1 = Room::new("standup".to_string());
+        let room2 = Room::new("standup".to_string());
+
+        let mut history1 = ChatHistory::open(temp_dir.path(), &room1)?;
+        history1.append_message(&Message::new("Hi".to_string(), "Alice".to_string()))?;
+
+        let mut history2 = ChatHistory::open(temp_dir.path(), &room2)?;
+        assert!(history2.load()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_skips_malformed_lines() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let room = Room::new("broken-room".to_string());
+
+        let mut history = ChatHistory::open(temp_dir.path(), &room)?;
+        history.append_message(&Message::new("Hi".to_string(), "Alice".to_string()))?;
+
+        let mut file = OpenOptions::new().append(true).open(&history.path)?;
+        file.write_all(b"{\"type\":\"mess")?;
+
+        assert_eq!(history.load()?.len(), 1);
+
+        // Appending after the partial line keeps the new record
+        history.append_message(&Message::new("Still here".to_string(), "Alice".to_string()))?;
+        let messages = history.load()?;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "Still here");
+
+        Ok(())
+    }
+}
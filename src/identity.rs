@@ -0,0 +1,214 @@
+use anyhow::{Context, Result};
+use p2panda_core::{PrivateKey, PublicKey};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The user's persistent peer identity, an Ed25519 key pair stored on disk.
+#[derive(Clone)]
+pub struct Identity {
+    private_key: PrivateKey,
+}
+
+// Only the public key, so the private key doesn't end up in logs
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("public_key", &self.public_key().to_hex())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Identity {
+    /// Load the identity from a key file, generating and saving a new one if it doesn't exist
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            let identity = Self {
+                private_key: PrivateKey::new(),
+            };
+            identity.save(path)?;
+            tracing::info!("Created new identity: {}", identity.public_key());
+            Ok(identity)
+        }
+    }
+
+    /// Load the identity from an existing key file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read identity file: {}", path.display()))?;
+
+        let bytes = hex::decode(content.trim())
+            .with_context(|| format!("Failed to decode identity file: {}", path.display()))?;
+        let private_key = PrivateKey::try_from(bytes.as_slice())
+            .with_context(|| format!("Invalid private key in: {}", path.display()))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(path)?.permissions().mode();
+            if mode & 0o077 != 0 {
+                tracing::warn!(
+                    "Identity file {} is accessible by other users (mode {:o})",
+                    path.display(),
+                    mode & 0o777
+                );
+            }
+        }
+
+        Ok(Self { private_key })
+    }
+
+    /// Replace the identity with a newly generated key. The previous key is kept next to it
+    /// with an `.old` extension, numbered if earlier keys were kept already.
+    pub fn rotate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            let previous = Self::load(path)?;
+            let backup_path = backup_path(path);
+            previous.save(&backup_path)?;
+            tracing::info!("Kept previous identity in: {}", backup_path.display());
+        }
+
+        let identity = Self {
+            private_key: PrivateKey::new(),
+        };
+        identity.save(path)?;
+        tracing::info!("Rotated identity to: {}", identity.public_key());
+        Ok(identity)
+    }
+
+    /// Save the private key to a file only readable by the current user
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create identity directory: {}", parent.display())
+            })?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Failed to open identity file: {}", path.display()))?;
+
+        // `mode` only applies when the file is created, so tighten existing files as well
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+
+        file.write_all(self.private_key.to_hex().as_bytes())
+            .with_context(|| format!("Failed to write identity file: {}", path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.private_key
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.private_key.public_key()
+    }
+}
+
+/// First free backup path for a key file, e.g. `identity.old`, then `identity.1.old`
+fn backup_path(path: &Path) -> PathBuf {
+    std::iter::once(path.with_extension("old"))
+        .chain((1..).map(|n| path.with_extension(format!("{}.old", n))))
+        .find(|backup_path| !backup_path.exists())
+        .expect("backup paths are unbounded")
+}
+
+/// Short, human-comparable fingerprint of a public key
+pub fn fingerprint(public_key: &PublicKey) -> String {
+    public_key.to_hex()[..8].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_identity_is_persistent() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("identity.key");
+
+        let created = Identity::load_or_create(&path)?;
+        let loaded = Identity::load_or_create(&path)?;
+        assert_eq!(created.public_key(), loaded.public_key());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identity_rotate() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("identity.key");
+
+        let original = Identity::load_or_create(&path)?;
+        let rotated = Identity::rotate(&path)?;
+        assert_ne!(original.public_key(), rotated.public_key());
+        assert_eq!(Identity::load(&path)?.public_key(), rotated.public_key());
+
+        // The previous key is kept
+        let previous = Identity::load(&path.with_extension("old"))?;
+        assert_eq!(previous.public_key(), original.public_key());
+
+        // Rotating again doesn't overwrite the first backup
+        let rotated_again = Identity::rotate(&path)?;
+        assert_eq!(
+            Identity::load(&path.with_extension("old"))?.public_key(),
+            original.public_key()
+        );
+        assert_eq!(
+            Identity::load(&path.with_extension("1.old"))?.public_key(),
+            rotated.public_key()
+        );
+        assert_eq!(
+            Identity::load(&path)?.public_key(),
+            rotated_again.public_key()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identity_debug_hides_private_key() {
+        let identity = Identity {
+            private_key: PrivateKey::new(),
+        };
+        let debug = format!("{:?}", identity);
+        assert!(debug.contains(&identity.public_key().to_hex()));
+        assert!(!debug.contains(&identity.private_key().to_hex()));
+    }
+
+    #[test]
+    fn test_identity_invalid_file() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("identity.key");
+        fs::write(&path, "not a key")?;
+
+        assert!(Identity::load(&path).is_err());
+
+        Ok(())
+    }
+}
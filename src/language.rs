@@ -0,0 +1,108 @@
+use whatlang::Lang;
+
+/// Minimum detection confidence to trust a result. Whatlang's own threshold rejects most chat
+/// sized messages, while wrong guesses below this one are common.
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// Detect the language of a text, returning its English name, e.g. "Spanish".
+///
+/// Returns `None` when the text is too short or ambiguous to tell reliably.
+pub fn detect_language(text: &str) -> Option<String> {
+    let info = whatlang::detect(text)?;
+    if info.confidence() < MIN_CONFIDENCE {
+        return None;
+    }
+
+    Some(info.lang().eng_name().to_string())
+}
+
+/// Whether two language names refer to the same language.
+///
+/// Besides English names, ISO 639-3 codes and native names are understood, so a configured
+/// target language of "spa" or "Español" matches a detected "Spanish".
+pub fn is_same_language(language: &str, other: &str) -> bool {
+    normalize_language(language) == normalize_language(other)
+}
+
+/// English names of the languages which can be detected, sorted alphabetically. These are the
+/// languages offered when choosing what to read messages in.
+pub fn supported_languages() -> Vec<&'static str> {
+    let mut languages: Vec<_> = Lang::all().iter().map(|lang| lang.eng_name()).collect();
+    languages.sort_unstable();
+    languages
+}
+
+/// Whether a text is written in a script used by a language, e.g. Cyrillic for Russian.
+///
+/// Returns `None` when the language is unknown or the script can't be told, so callers only
+/// act on a clear mismatch.
+pub fn is_written_in(text: &str, language: &str) -> Option<bool> {
+    let lang = find_lang(language)?;
+    let script = whatlang::detect_script(text)?;
+    Some(script.langs().contains(&lang))
+}
+
+fn find_lang(language: &str) -> Option<Lang> {
+    let language = language.trim().to_lowercase();
+    Lang::all().iter().copied().find(|lang| {
+        lang.eng_name().to_lowercase() == language
+            || lang.code() == language
+            || lang.name().to_lowercase() == language
+    })
+}
+
+/// The form in which language names are compared by [`is_same_language`], for keys which
+/// should match regardless of how a language is spelled.
+pub fn normalize_language(language: &str) -> String {
+    match find_lang(language) {
+        Some(lang) => lang.eng_name().to_lowercase(),
+        None => language.trim().to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            detect_language("¿Dónde está la biblioteca? Necesito devolver estos libros hoy.")
+                .as_deref(),
+            Some("Spanish")
+        );
+        assert_eq!(
+            detect_language("Where is the library? I need to return these books today.").as_deref(),
+            Some("English")
+        );
+
+        // Too short to tell
+        assert!(detect_language("ok").is_none());
+    }
+
+    #[test]
+    fn test_is_same_language() {
+        assert!(is_same_language("Spanish", "spanish "));
+        assert!(is_same_language("Spanish", "spa"));
+        assert!(is_same_language("Spanish", "Español"));
+        assert!(!is_same_language("Spanish", "French"));
+
+        // Languages unknown to the detector still compare by name
+        assert!(is_same_language("Klingon", "klingon"));
+    }
+
+    #[test]
+    fn test_is_written_in() {
+        assert_eq!(is_written_in("Привет, как дела?", "Russian"), Some(true));
+        assert_eq!(is_written_in("Hello, how are you?", "Russian"), Some(false));
+        assert_eq!(is_written_in("こんにちは", "Japanese"), Some(true));
+        assert_eq!(is_written_in("Hello", "Klingon"), None);
+    }
+
+    #[test]
+    fn test_supported_languages() {
+        let languages = supported_languages();
+        assert!(languages.contains(&"Spanish"));
+        assert!(languages.is_sorted());
+    }
+}
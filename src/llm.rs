@@ -1,10 +1,114 @@
-use anyhow::Result;
-use kalosm::language::{ChatModelExt, Llama, ModelBuilder};
-use tokio::sync::OnceCell;
-use tracing::{debug, instrument};
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use kalosm::language::{
+    ChatModelExt, FileSource, GenerationParameters, Llama, LlamaSource, ModelLoadingProgress,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tokio::sync::{OnceCell, watch};
+use tracing::{debug, error, instrument, warn};
+
+use crate::config::{LlamaConfig, LlamaModel, ModelSource, SamplerConfig};
 
 static LLAMA: OnceCell<Llama> = OnceCell::const_new();
 
+/// A model file given in the config can't be used.
+#[derive(Debug)]
+pub enum ModelFileError {
+    Missing(PathBuf),
+    Unreadable(PathBuf, io::Error),
+    /// The file doesn't start like a GGUF file
+    NotGguf(PathBuf),
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    /// The file looked fine, but the model could not be loaded from it
+    Corrupt(PathBuf, String),
+}
+
+impl fmt::Display for ModelFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(path) => write!(f, "model file {} does not exist", path.display()),
+            Self::Unreadable(path, e) => {
+                write!(f, "model file {} can't be read: {}", path.display(), e)
+            }
+            Self::NotGguf(path) => write!(f, "model file {} is not a GGUF file", path.display()),
+            Self::ChecksumMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "model file {} has SHA-256 checksum {} instead of {}, it may be incomplete or corrupt",
+                path.display(),
+                actual,
+                expected
+            ),
+            Self::Corrupt(path, e) => {
+                write!(
+                    f,
+                    "model file {} could not be loaded: {}",
+                    path.display(),
+                    e
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelFileError {}
+
+/// Check that a model file exists, is a GGUF file and, if a checksum is given, matches it.
+/// Hashing reads the whole file, so this blocks for a while on large models.
+pub fn verify_model_file(path: &Path, sha256: Option<&str>) -> Result<(), ModelFileError> {
+    let unreadable = |e: io::Error| {
+        if e.kind() == io::ErrorKind::NotFound {
+            ModelFileError::Missing(path.to_path_buf())
+        } else {
+            ModelFileError::Unreadable(path.to_path_buf(), e)
+        }
+    };
+
+    let mut file = File::open(path).map_err(unreadable)?;
+    let mut magic = [0; 4];
+    if file.read_exact(&mut magic).is_err() || &magic != b"GGUF" {
+        return Err(ModelFileError::NotGguf(path.to_path_buf()));
+    }
+
+    let Some(expected) = sha256 else {
+        return Ok(());
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(magic);
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer).map_err(unreadable)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    let actual = hex::encode(hasher.finalize());
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(ModelFileError::ChecksumMismatch {
+            path: path.to_path_buf(),
+            expected: expected.trim().to_lowercase(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
 /// Llm completes tasks by generating text.
 pub trait Llm {
     async fn run_task(
@@ -12,47 +116,434 @@ pub trait Llm {
         task_description: impl ToString,
         task_input_text: impl ToString,
     ) -> Result<String>;
+
+    /// Like [`Llm::run_task`], passing each piece of text to `on_token` as it is generated.
+    /// Llms which can't stream pass the whole output at once.
+    async fn run_task_streaming(
+        &self,
+        task_description: impl ToString,
+        task_input_text: impl ToString,
+        mut on_token: impl FnMut(&str) + Send,
+    ) -> Result<String> {
+        let output = self.run_task(task_description, task_input_text).await?;
+        on_token(&output);
+        Ok(output)
+    }
+}
+
+/// Llm running a kalosm Llama model in this process, with the configured sampling settings.
+#[derive(Clone)]
+pub struct LocalLlama {
+    model: Llama,
+    max_tokens: Option<u32>,
+    sampler: SamplerConfig,
+}
+
+impl LocalLlama {
+    pub fn new(model: Llama, config: &LlamaConfig) -> Self {
+        Self {
+            model,
+            max_tokens: config.max_tokens,
+            sampler: config.sampler.clone(),
+        }
+    }
+
+    fn generation_parameters(&self) -> GenerationParameters {
+        let mut parameters = GenerationParameters::default();
+        if let Some(temperature) = self.sampler.temperature {
+            parameters = parameters.with_temperature(temperature);
+        }
+        if let Some(repetition_penalty) = self.sampler.repetition_penalty {
+            parameters = parameters.with_repetition_penalty(repetition_penalty);
+        }
+        parameters.with_seed(self.sampler.seed)
+    }
+
+    fn count_tokens(&self, text: &str) -> Result<u32> {
+        let encoding = self
+            .model
+            .tokenizer()
+            .encode(text, false)
+            .map_err(|e| anyhow::anyhow!("Failed to tokenize prompt: {}", e))?;
+        Ok(encoding.len() as u32)
+    }
 }
 
-impl Llm for Llama {
+impl Llm for LocalLlama {
     /// Generate text using guidelines and input text.
     async fn run_task(&self, guidelines: impl ToString, input: impl ToString) -> Result<String> {
-        self.task(guidelines)
+        self.run_task_streaming(guidelines, input, |_| {}).await
+    }
+
+    async fn run_task_streaming(
+        &self,
+        guidelines: impl ToString,
+        input: impl ToString,
+        mut on_token: impl FnMut(&str) + Send,
+    ) -> Result<String> {
+        let guidelines = guidelines.to_string();
+        let input = input.to_string();
+
+        let mut parameters = self.generation_parameters();
+        if let Some(max_tokens) = self.max_tokens {
+            let prompt_tokens = self.count_tokens(&guidelines)? + self.count_tokens(&input)?;
+            if prompt_tokens >= max_tokens {
+                anyhow::bail!(
+                    "Prompt of {} tokens exceeds the maximum of {} tokens",
+                    prompt_tokens,
+                    max_tokens
+                );
+            }
+            parameters = parameters.with_max_length(max_tokens - prompt_tokens);
+        }
+
+        let mut response = self
+            .model
+            .task(guidelines)
             .run(input)
+            .with_sampler(parameters);
+        while let Some(token) = response.next().await {
+            on_token(&token);
+        }
+        // The stream ends when generation is done, the response then holds the result
+        response.await.map_err(anyhow::Error::from)
+    }
+}
+
+/// Llm served by an OpenAI-compatible chat completions endpoint, such as llama.cpp or Ollama.
+#[derive(Debug, Clone)]
+pub struct OpenAiCompatible {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: [ChatMessage; 2],
+    temperature: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatCompletionChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionChoice {
+    message: ChatMessage,
+}
+
+impl OpenAiCompatible {
+    /// Client for the API at the given base URL, e.g. http://localhost:8080/v1
+    pub fn new(url: &str, model: &str, api_key: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key,
+        }
+    }
+}
+
+impl Llm for OpenAiCompatible {
+    /// Generate text with the guidelines as system prompt and the input as user message.
+    async fn run_task(&self, guidelines: impl ToString, input: impl ToString) -> Result<String> {
+        let request = ChatCompletionRequest {
+            model: &self.model,
+            messages: [
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: guidelines.to_string(),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: input.to_string(),
+                },
+            ],
+            temperature: 0.0,
+        };
+
+        let mut builder = self
+            .client
+            .post(format!("{}/chat/completions", self.url))
+            .json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let response: ChatCompletionResponse = builder
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.url))?
+            .error_for_status()?
+            .json()
             .await
-            .map_err(anyhow::Error::from)
+            .context("Invalid chat completion response")?;
+
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .context("Chat completion response has no choices")
     }
 }
 
-/// Ensure that all AI models are present.
-#[instrument]
-pub async fn ensure_ai_models_present() -> Result<()> {
-    debug!("Ensuring models are downloaded");
-    if Llama::builder().requires_download() {
-        debug!("Ensuring models are downloaded");
-        let _ = get_llm().await?;
+/// Where kalosm loads a model from
+fn llama_source(source: &ModelSource) -> LlamaSource {
+    match source {
+        ModelSource::Preset(model) => preset_source(*model),
+        ModelSource::File { path, .. } => LlamaSource::new(FileSource::local(path.clone())),
+        ModelSource::HuggingFace {
+            repo,
+            file,
+            revision,
+        } => LlamaSource::new(FileSource::huggingface(repo, revision, file)),
     }
-    debug!("All models are downloaded");
+}
 
-    Ok(())
+fn preset_source(model: LlamaModel) -> LlamaSource {
+    match model {
+        LlamaModel::Llama3_1_8bChat => LlamaSource::llama_3_1_8b_chat(),
+        LlamaModel::Llama3_2_3bChat => LlamaSource::llama_3_2_3b_chat(),
+        LlamaModel::Llama3_2_1bChat => LlamaSource::llama_3_2_1b_chat(),
+        LlamaModel::Phi3_5Mini => LlamaSource::phi_3_5_mini_4k_instruct(),
+        LlamaModel::Qwen2_5_3bInstruct => LlamaSource::qwen_2_5_3b_instruct(),
+        LlamaModel::Qwen2_5_1_5bInstruct => LlamaSource::qwen_2_5_1_5b_instruct(),
+    }
+}
+
+/// State of loading the local model, reported while the chat is already usable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelStatus {
+    /// Checking the model file against its checksum
+    Verifying,
+    Downloading {
+        /// Description of the file being downloaded
+        source: String,
+        downloaded: u64,
+        total: u64,
+    },
+    /// Loading the model into memory, from 0 to 1
+    Loading(f32),
+    /// Running a first task, so the first translation doesn't pay for it
+    Warming,
+    Ready,
+    Failed(String),
+}
+
+/// Download, load and warm the local model in the background, reporting progress on the
+/// returned channel.
+pub fn load_ai_models(config: LlamaConfig) -> watch::Receiver<ModelStatus> {
+    let (status_tx, status_rx) = watch::channel(ModelStatus::Loading(0.0));
+
+    tokio::spawn(async move {
+        let status = match load_and_warm(&config, &status_tx).await {
+            Ok(()) => ModelStatus::Ready,
+            Err(e) => {
+                error!("Failed to load model: {:#}", e);
+                ModelStatus::Failed(format!("{:#}", e))
+            }
+        };
+        let _ = status_tx.send(status);
+    });
+
+    status_rx
 }
 
-/// Warm AI models so they respond quickly.
-#[instrument]
-pub async fn warm_ai_models() -> Result<()> {
-    let llm = get_llm().await?;
+#[instrument(skip(status_tx))]
+async fn load_and_warm(config: &LlamaConfig, status_tx: &watch::Sender<ModelStatus>) -> Result<()> {
+    if let ModelSource::File { path, sha256 } = &config.model {
+        debug!("Verifying model file");
+        let _ = status_tx.send(ModelStatus::Verifying);
+        let (path, sha256) = (path.clone(), sha256.clone());
+        tokio::task::spawn_blocking(move || verify_model_file(&path, sha256.as_deref())).await??;
+    }
+
+    let progress_tx = status_tx.clone();
+    let llm = get_llm(config, move |progress| {
+        let status = match progress {
+            ModelLoadingProgress::Downloading { source, progress } => ModelStatus::Downloading {
+                source,
+                downloaded: progress.progress,
+                total: progress.size,
+            },
+            ModelLoadingProgress::Loading { progress } => ModelStatus::Loading(progress),
+        };
+        let _ = progress_tx.send(status);
+    })
+    .await?;
 
     debug!("Warming Llama instance");
+    let _ = status_tx.send(ModelStatus::Warming);
     llm.task("Say hello back.").run("Hello!").await?;
     debug!("Warmed Llama instance");
 
     Ok(())
 }
 
-/// Get the lazily initialized Llama instance.
-pub async fn get_llm() -> Result<&'static Llama> {
+/// Use the given number of CPU threads for inference. Must be called before a model is loaded.
+pub fn set_inference_threads(threads: usize) {
+    // candle, which runs the models, parallelizes on the global rayon pool
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        warn!("Failed to set the number of inference threads: {}", e);
+    }
+}
+
+/// Get the lazily initialized Llama instance. The model of the first call is loaded, which
+/// reports its download and loading progress to the given handler.
+pub async fn get_llm(
+    config: &LlamaConfig,
+    on_progress: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+) -> Result<&'static Llama> {
     LLAMA
-        .get_or_try_init(|| async { Llama::new_chat().await })
+        .get_or_try_init(|| async {
+            Llama::builder()
+                .with_source(llama_source(&config.model))
+                .build_with_loading_handler(on_progress)
+                .await
+        })
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to initialize model: {}", e))
+        .map_err(|e| match &config.model {
+            ModelSource::File { path, .. } => {
+                ModelFileError::Corrupt(path.clone(), e.to_string()).into()
+            }
+            _ => anyhow::anyhow!("Failed to initialize model: {}", e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Stand-in for an OpenAI-compatible server, answering one request with the given content.
+    /// Returns the server's base URL and the request body it received.
+    async fn serve_completion(content: &str) -> Result<(String, tokio::task::JoinHandle<String>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/v1", listener.local_addr()?);
+        let body = serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": content } }]
+        })
+        .to_string();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            // Read until the whole body announced by Content-Length arrived
+            loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((headers, request_body)) = text.split_once("\r\n\r\n") {
+                    let length = headers
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|value| value.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if request_body.len() >= length {
+                        break;
+                    }
+                }
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        Ok((url, server))
+    }
+
+    #[test]
+    fn test_verify_model_file() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("model.gguf");
+
+        let err = verify_model_file(&path, None).unwrap_err();
+        assert!(matches!(err, ModelFileError::Missing(_)));
+
+        std::fs::write(&path, b"<html>Not found</html>")?;
+        let err = verify_model_file(&path, None).unwrap_err();
+        assert!(matches!(err, ModelFileError::NotGguf(_)));
+
+        std::fs::write(&path, b"GGUF model")?;
+        let checksum = hex::encode(Sha256::digest(b"GGUF model"));
+        verify_model_file(&path, None)?;
+        verify_model_file(&path, Some(&checksum.to_uppercase()))?;
+
+        let err = verify_model_file(&path, Some(&"00".repeat(32))).unwrap_err();
+        match err {
+            ModelFileError::ChecksumMismatch { actual, .. } => assert_eq!(actual, checksum),
+            err => panic!("Unexpected error: {err}"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_model_file_fails() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config = LlamaConfig {
+            model: ModelSource::File {
+                path: temp_dir.path().join("missing.gguf"),
+                sha256: None,
+            },
+            ..LlamaConfig::default()
+        };
+
+        let mut status_rx = load_ai_models(config);
+        let status = status_rx
+            .wait_for(|status| matches!(status, ModelStatus::Ready | ModelStatus::Failed(_)))
+            .await?
+            .clone();
+        match status {
+            ModelStatus::Failed(message) => assert!(message.contains("does not exist")),
+            status => panic!("Unexpected status: {status:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_run_task() -> Result<()> {
+        let (url, server) = serve_completion("Bonjour").await?;
+        let llm = OpenAiCompatible::new(&url, "tiny-model", Some("secret".to_string()));
+
+        let output = llm.run_task("Translate to French", "Hello").await?;
+        assert_eq!(output, "Bonjour");
+
+        let request = server.await?;
+        assert!(request.starts_with("POST /v1/chat/completions"));
+        assert!(
+            request
+                .to_lowercase()
+                .contains("authorization: bearer secret")
+        );
+        assert!(request.contains(r#""model":"tiny-model""#));
+        assert!(request.contains(r#""content":"Hello""#));
+
+        Ok(())
+    }
 }
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
@@ -9,20 +9,26 @@ use ratatui::{Terminal, backend::CrosstermBackend};
 use std::fs::OpenOptions;
 use std::io;
 use std::path::{Path, PathBuf};
-use tokio::task;
 use tracing::info;
 
 mod config;
 mod entities;
+mod history;
+mod identity;
+mod language;
 mod llm;
 mod p2p;
 mod room_manager;
+mod saved_rooms;
 mod translation;
+mod translation_cache;
 mod translation_service;
+mod translation_validation;
 mod tui;
 
 use crate::translation_service::disable_translation_worker;
-use config::Config;
+use config::{Config, TranslationBackendConfig};
+use identity::Identity;
 use tui::TuiApp;
 
 #[derive(Parser)]
@@ -35,6 +41,41 @@ struct Args {
     /// Path to config file. If not provided, uses ~/.config/puf/config.toml
     #[arg(short, long)]
     config: Option<PathBuf>,
+
+    /// GGUF model file to translate with, instead of the model in the config. Works without
+    /// network access.
+    #[arg(long)]
+    model: Option<PathBuf>,
+
+    /// SHA-256 checksum the model file is verified against
+    #[arg(long, requires = "model")]
+    model_sha256: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage your persistent peer identity
+    Identity {
+        #[command(subcommand)]
+        action: IdentityAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum IdentityAction {
+    /// Show your public key, which identifies you to other peers
+    Show,
+    /// Export your private key to stdout or a file
+    Export {
+        /// File to write the private key to. It is created with owner-only permissions.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Replace your key with a newly generated one. The previous key is kept with an .old extension, numbered if one exists.
+    Rotate,
 }
 
 #[tokio::main]
@@ -44,19 +85,26 @@ async fn main() -> Result<()> {
     maybe_init_logging(&args)?;
 
     // Load configuration
-    let config = Config::load(args.config.clone())?;
+    let mut config = Config::load(args.config.clone())?;
+    if let Some(model) = args.model.clone() {
+        config.use_model_file(model, args.model_sha256.clone());
+    }
     info!(
         "Loaded config: disable_ai={}, username={}",
         config.disable_ai, config.username
     );
 
-    // Conditionally load AI models based on config
+    if let Some(command) = args.command {
+        return run_command(command, &config);
+    }
+
+    // AI models are loaded by the translation service, which shows the progress in the UI
     if !config.disable_ai {
-        // Ensure all AI models are downloaded before taking over the terminal
-        llm::ensure_ai_models_present().await?;
-        // Warm LLMs in the background.
-        // Future idea: Consider showing the state of this in the UI
-        task::spawn(llm::warm_ai_models());
+        if let TranslationBackendConfig::Llama(llama_config) = &config.translation_backend
+            && let Some(threads) = llama_config.threads
+        {
+            llm::set_inference_threads(threads);
+        }
     } else {
         info!("AI/LLM functionality disabled by config");
         disable_translation_worker()?;
@@ -87,6 +135,36 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+fn run_command(command: Command, config: &Config) -> Result<()> {
+    match command {
+        Command::Identity { action } => {
+            let identity_path = config.identity_path()?;
+            match action {
+                IdentityAction::Show => {
+                    let identity = Identity::load_or_create(&identity_path)?;
+                    println!("{}", identity.public_key());
+                }
+                IdentityAction::Export { output } => {
+                    let identity = Identity::load_or_create(&identity_path)?;
+                    match output {
+                        Some(path) => {
+                            identity.save(&path)?;
+                            println!("Exported private key to {}", path.display());
+                        }
+                        None => println!("{}", identity.private_key()),
+                    }
+                }
+                IdentityAction::Rotate => {
+                    let identity = Identity::rotate(&identity_path)?;
+                    println!("{}", identity.public_key());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn maybe_init_logging(args: &Args) -> Result<()> {
     // Only initialize tracing if log-file is provided
     if let Some(log_file_path) = &args.log_file {
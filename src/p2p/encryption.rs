@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::fmt;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// A sealed payload could not be opened with the room key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecryptionError;
+
+impl fmt::Display for DecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "payload could not be decrypted with the room key")
+    }
+}
+
+impl std::error::Error for DecryptionError {}
+
+/// Symmetric key shared by everyone who knows a room's identifier.
+///
+/// Payloads are sealed with XChaCha20-Poly1305 using a random nonce which is prepended to the
+/// ciphertext.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RoomKey([u8; KEY_LEN]);
+
+impl RoomKey {
+    /// Generate a new random room key.
+    pub fn generate() -> Self {
+        Self(XChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    /// Parse a room key from its hex representation.
+    pub fn from_hex(value: &str) -> Result<Self> {
+        let bytes = hex::decode(value).context("Room key is not valid hex")?;
+        let key: [u8; KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Room key must be {} bytes", KEY_LEN))?;
+        Ok(Self(key))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// Encrypt a payload. `associated_data` is authenticated but not encrypted.
+    pub fn seal(&self, plaintext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>> {
+        let cipher = XChaCha20Poly1305::new(&self.0.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt payload"))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt a payload sealed with [`RoomKey::seal`], rejecting it if it was tampered with or
+    /// sealed with another key or associated data.
+    pub fn open(&self, sealed: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(DecryptionError);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let cipher = XChaCha20Poly1305::new(&self.0.into());
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| DecryptionError)
+    }
+}
+
+impl fmt::Debug for RoomKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key material
+        write!(f, "RoomKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = RoomKey::generate();
+        let sealed = key.seal(b"Hello", b"room").unwrap();
+        assert_ne!(&sealed[NONCE_LEN..], b"Hello");
+        assert_eq!(key.open(&sealed, b"room").unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_key_or_tampering() {
+        let key = RoomKey::generate();
+        let sealed = key.seal(b"Hello", b"room").unwrap();
+
+        assert_eq!(
+            RoomKey::generate().open(&sealed, b"room"),
+            Err(DecryptionError)
+        );
+        assert_eq!(key.open(&sealed, b"other-room"), Err(DecryptionError));
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(key.open(&tampered, b"room"), Err(DecryptionError));
+        assert_eq!(key.open(&sealed[..4], b"room"), Err(DecryptionError));
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let key = RoomKey::generate();
+        assert_eq!(RoomKey::from_hex(&key.to_hex()).unwrap(), key);
+        assert!(RoomKey::from_hex("abcd").is_err());
+        assert!(RoomKey::from_hex("not hex").is_err());
+    }
+}
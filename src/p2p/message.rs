@@ -1,12 +1,33 @@
+use p2panda_core::{PublicKey, RawOperation};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::SystemTime;
 
+use super::presence::SealedAnnouncement;
+
+/// Everything peers publish on a chat group's gossip topic.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum GossipMessage {
+    /// A signed chat operation, which peers also exchange via sync
+    Operation(RawOperation),
+    /// A peer announcing itself, which is never stored
+    Announcement(SealedAnnouncement),
+}
+
 /// Represents a chat message that can be sent over the p2p network.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NetworkMessage {
     pub content: String,
     pub timestamp: SystemTime,
+    /// Display name chosen by the sender
     pub sender_id: String,
+    /// Public key of the author, checked against the operation signature on receipt
+    #[serde(default)]
+    pub author: Option<PublicKey>,
+    /// Translations made by the sender, keyed by language. Receivers use a matching one instead
+    /// of translating locally.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub translations: BTreeMap<String, String>,
 }
 
 impl NetworkMessage {
@@ -15,6 +36,8 @@ impl NetworkMessage {
             content,
             timestamp: SystemTime::now(),
             sender_id,
+            author: None,
+            translations: BTreeMap::new(),
         }
     }
 }
@@ -1,11 +1,16 @@
 pub mod chat_group;
+pub mod encryption;
 pub mod message;
 pub mod network;
+pub mod operations;
+pub mod presence;
 pub mod service;
 pub mod task;
 pub mod types;
 
 pub use chat_group::ChatGroup;
-pub use message::NetworkMessage;
+pub use encryption::RoomKey;
+pub use message::{GossipMessage, NetworkMessage};
+pub use presence::PeerAnnouncement;
 pub use service::ChatNetworkService;
 pub use types::{NetworkCommand, NetworkError, NetworkEvent};
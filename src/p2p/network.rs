@@ -1,29 +1,212 @@
-use anyhow::Result;
-use p2panda_core::PrivateKey;
+use anyhow::{Context, Result};
+use p2panda_core::{Hash, PrivateKey, PublicKey};
 use p2panda_discovery::mdns::LocalDiscovery;
-use p2panda_net::{Network, NetworkBuilder};
+use p2panda_net::{
+    Network, NetworkBuilder, NetworkId, RelayUrl, ResyncConfiguration, SyncConfiguration,
+};
+use std::str::FromStr;
 
+use crate::config::Config;
 use crate::p2p::ChatGroup;
+use crate::p2p::operations::ChatOperations;
 
-/// Initialize a basic p2panda network with mDNS discovery.
-pub async fn create_network() -> Result<Network<ChatGroup>> {
+/// STUN port of relay servers. The port of the relay URL is the HTTPS port, not this one.
+const DEFAULT_STUN_PORT: u16 = 3478;
+
+/// Network id shared by all peers which don't configure a network passphrase
+const PUBLIC_NETWORK_ID: NetworkId = [1; 32];
+
+/// Initialize a p2panda network with log sync of chat operations.
+///
+/// Peers are found via mDNS on the local network and via the bootstrap peers and relay from the
+/// config, so people on different networks can chat.
+pub async fn create_network(
+    config: &Config,
+    private_key: PrivateKey,
+    operations: &ChatOperations,
+) -> Result<Network<ChatGroup>> {
     // Peers using the same "network id" will eventually find each other. This
     // is the most global identifier to group peers into multiple networks when
     // necessary.
-    let network_id = [1; 32];
+    let network_id = network_id(config.network_passphrase.as_deref());
+
+    // Catch up on operations we missed when joining a chat group, and periodically afterwards
+    // so peers reconcile after being offline.
+    let sync_config =
+        SyncConfiguration::new(operations.sync_protocol()).resync(ResyncConfiguration::new());
+
+    let mut builder = NetworkBuilder::new(network_id)
+        .private_key(private_key)
+        .sync(sync_config);
 
-    // Generate an Ed25519 private key which will be used to authenticate your peer towards others.
-    let private_key = PrivateKey::new();
+    if config.bootstrap {
+        builder = builder.bootstrap();
+    }
 
     // Use mDNS to discover other peers on the local network.
-    let mdns_discovery = LocalDiscovery::new();
+    if config.local_discovery {
+        builder = builder.discovery(LocalDiscovery::new());
+    }
+
+    if let Some(relay_url) = &config.relay_url {
+        let relay_url = parse_relay_url(relay_url)?;
+        builder = builder.relay(relay_url, false, DEFAULT_STUN_PORT);
+    }
+
+    for peer in &config.bootstrap_peers {
+        let public_key = PublicKey::from_str(&peer.node_id)
+            .with_context(|| format!("Invalid node id of bootstrap peer: {}", peer.node_id))?;
+        let relay_url = peer.relay_url.as_deref().map(parse_relay_url).transpose()?;
+        builder = builder.direct_address(public_key, peer.addresses.clone(), relay_url);
+    }
 
     // Establish the p2p network which will automatically connect to any discovered peers.
-    let network = NetworkBuilder::new(network_id.into())
-        .private_key(private_key)
-        .discovery(mdns_discovery)
-        .build()
-        .await?;
+    let network = builder.build().await?;
 
     Ok(network)
 }
+
+/// Derive the network id from a passphrase, so only peers who know it meet each other. Without a
+/// passphrase everyone joins the public network.
+fn network_id(passphrase: Option<&str>) -> NetworkId {
+    match passphrase {
+        Some(passphrase) => Hash::new(passphrase.as_bytes()).into(),
+        None => PUBLIC_NETWORK_ID,
+    }
+}
+
+fn parse_relay_url(url: &str) -> Result<RelayUrl> {
+    RelayUrl::from_str(url).with_context(|| format!("Invalid relay URL: {}", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BootstrapPeer;
+    use crate::p2p::{GossipMessage, NetworkMessage, RoomKey};
+    use p2panda_core::Hash;
+    use p2panda_core::cbor::{decode_cbor, encode_cbor};
+    use p2panda_net::{FromNetwork, ToNetwork};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn loopback_config() -> Config {
+        Config {
+            local_discovery: false,
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_peer_on_loopback() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let chat_group = ChatGroup::from_hash(Hash::new(b"loopback"));
+        let room_key = RoomKey::generate();
+
+        let key_a = PrivateKey::new();
+        let mut operations_a = ChatOperations::open(&temp_dir.path().join("a.sqlite")).await?;
+        let config_a = Config {
+            bootstrap: true,
+            ..loopback_config()
+        };
+        let network_a = create_network(&config_a, key_a.clone(), &operations_a).await?;
+
+        // Node B only knows node A by its id and loopback address, mDNS is disabled on both
+        let port = network_a
+            .direct_addresses()
+            .await
+            .and_then(|addresses| addresses.iter().find(|a| a.is_ipv4()).map(|a| a.port()))
+            .context("Node A has no IPv4 address")?;
+        let config_b = Config {
+            bootstrap_peers: vec![BootstrapPeer {
+                node_id: key_a.public_key().to_hex(),
+                addresses: vec![SocketAddr::from(([127, 0, 0, 1], port))],
+                relay_url: None,
+            }],
+            ..loopback_config()
+        };
+        let mut operations_b = ChatOperations::open(&temp_dir.path().join("b.sqlite")).await?;
+        let network_b = create_network(&config_b, PrivateKey::new(), &operations_b).await?;
+
+        let (tx_a, _rx_a, ready_a) = network_a.subscribe(chat_group.clone()).await?;
+        let (_tx_b, mut rx_b, ready_b) = network_b.subscribe(chat_group.clone()).await?;
+
+        let received = tokio::time::timeout(Duration::from_secs(30), async {
+            ready_a.await?;
+            ready_b.await?;
+
+            let mut message = NetworkMessage::new("Hello".to_string(), "Alice".to_string());
+            message.author = Some(key_a.public_key());
+            let operation = operations_a
+                .publish(&key_a, &chat_group, &room_key, &message)
+                .await?;
+            tx_a.send(ToNetwork::Message {
+                bytes: encode_cbor(&GossipMessage::Operation(operation))?,
+            })
+            .await?;
+
+            // The message arrives via gossip or sync, whichever is first
+            while let Some(from_network) = rx_b.recv().await {
+                let (header, payload) = match from_network {
+                    FromNetwork::GossipMessage { bytes, .. } => {
+                        match decode_cbor::<GossipMessage, _>(&bytes[..])? {
+                            GossipMessage::Operation(operation) => operation,
+                            GossipMessage::Announcement(_) => continue,
+                        }
+                    }
+                    FromNetwork::SyncMessage {
+                        header, payload, ..
+                    } => (header, payload),
+                };
+                if let Some(message) = operations_b
+                    .ingest(&chat_group, &room_key, &header, payload.as_deref())
+                    .await?
+                {
+                    return Ok::<_, anyhow::Error>(message);
+                }
+            }
+            anyhow::bail!("Subscription closed")
+        })
+        .await
+        .context("Timed out waiting for message from bootstrap peer")??;
+
+        assert_eq!(received.content, "Hello");
+        assert_eq!(received.author, Some(key_a.public_key()));
+
+        network_a.shutdown().await?;
+        network_b.shutdown().await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_network_id_from_passphrase() {
+        assert_eq!(network_id(None), PUBLIC_NETWORK_ID);
+        assert_eq!(network_id(Some("acme")), network_id(Some("acme")));
+        assert_ne!(network_id(Some("acme")), network_id(Some("globex")));
+        assert_ne!(network_id(Some("acme")), PUBLIC_NETWORK_ID);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_bootstrap_peer() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let operations = ChatOperations::open(&temp_dir.path().join("ops.sqlite")).await?;
+        let config = Config {
+            bootstrap_peers: vec![BootstrapPeer {
+                node_id: "not a key".to_string(),
+                addresses: Vec::new(),
+                relay_url: None,
+            }],
+            ..loopback_config()
+        };
+
+        assert!(
+            create_network(&config, PrivateKey::new(), &operations)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,525 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use p2panda_core::{
+    Body, Hash, Header, Operation, PrivateKey, PublicKey, RawOperation, validate_backlink,
+    validate_operation,
+};
+use p2panda_store::sqlite::store::{connection_pool, create_database, run_pending_migrations};
+use p2panda_store::{LogStore, OperationStore, SqliteStore};
+use p2panda_sync::log_sync::{LogSyncProtocol, TopicLogMap};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::p2p::{ChatGroup, NetworkMessage, RoomKey};
+
+/// Persistent store of signed chat operations. Each author has one log per chat group.
+pub type ChatStore = SqliteStore<ChatGroup, ()>;
+
+/// Log-height sync protocol for chat operations.
+pub type ChatSyncProtocol = LogSyncProtocol<ChatTopicMap, ChatGroup, (), ChatStore>;
+
+/// An operation whose signature or claimed author could not be verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthenticationError {
+    /// The header signature does not match the header's public key
+    SignatureMismatch,
+    /// The message claims a different author than the key which signed it
+    AuthorMismatch,
+}
+
+impl fmt::Display for AuthenticationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SignatureMismatch => write!(f, "operation signature does not match its author"),
+            Self::AuthorMismatch => write!(f, "message author does not match operation signer"),
+        }
+    }
+}
+
+impl std::error::Error for AuthenticationError {}
+
+/// Where an operation delivered ahead of a gap belongs in its log
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LogPosition {
+    public_key: PublicKey,
+    chat_group: ChatGroup,
+    seq_num: u64,
+}
+
+/// Maps a chat group to the logs of every author who wrote to it.
+#[derive(Clone, Debug)]
+pub struct ChatTopicMap {
+    store: ChatStore,
+}
+
+#[async_trait]
+impl TopicLogMap<ChatGroup, ChatGroup> for ChatTopicMap {
+    async fn get(&self, topic: &ChatGroup) -> Option<HashMap<PublicKey, Vec<ChatGroup>>> {
+        match self.store.get_log_heights(topic).await {
+            Ok(heights) => Some(
+                heights
+                    .into_iter()
+                    .map(|(public_key, _)| (public_key, vec![topic.clone()]))
+                    .collect(),
+            ),
+            Err(e) => {
+                tracing::error!("Failed to read log heights: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Local, append-only log of signed chat operations.
+#[derive(Clone, Debug)]
+pub struct ChatOperations {
+    store: ChatStore,
+    /// Operations delivered ahead of a gap in their log. They were already shown but can only
+    /// be stored once sync fills the gap. Kept in a file next to the store, so they aren't
+    /// shown again when the gap is filled after a restart.
+    delivered_ahead: HashMap<Hash, LogPosition>,
+    delivered_ahead_path: PathBuf,
+}
+
+impl ChatOperations {
+    /// Open the operation store at the given path, creating it if it doesn't exist.
+    pub async fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create store directory: {}", parent.display())
+            })?;
+        }
+
+        let url = format!("sqlite://{}", path.display());
+        create_database(&url)
+            .await
+            .with_context(|| format!("Failed to create operation store: {}", path.display()))?;
+        let pool = connection_pool(&url, 1)
+            .await
+            .with_context(|| format!("Failed to open operation store: {}", path.display()))?;
+        run_pending_migrations(&pool)
+            .await
+            .context("Failed to migrate operation store")?;
+
+        let delivered_ahead_path = path.with_extension("delivered_ahead.json");
+        let delivered_ahead = if delivered_ahead_path.exists() {
+            let json = std::fs::read_to_string(&delivered_ahead_path).with_context(|| {
+                format!(
+                    "Failed to read delivered operations: {}",
+                    delivered_ahead_path.display()
+                )
+            })?;
+            serde_json::from_str(&json).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring malformed delivered operations: {}", e);
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+
+        let mut operations = Self {
+            store: SqliteStore::new(pool),
+            delivered_ahead,
+            delivered_ahead_path,
+        };
+
+        // Drop operations whose gap was filled before they were forgotten
+        let mut filled = Vec::new();
+        for (hash, position) in &operations.delivered_ahead {
+            let latest = operations
+                .store
+                .latest_operation(&position.public_key, &position.chat_group)
+                .await?;
+            if latest.is_some_and(|(latest, _)| latest.seq_num >= position.seq_num) {
+                filled.push(*hash);
+            }
+        }
+        if !filled.is_empty() {
+            for hash in &filled {
+                operations.delivered_ahead.remove(hash);
+            }
+            operations.save_delivered_ahead()?;
+        }
+
+        Ok(operations)
+    }
+
+    fn save_delivered_ahead(&self) -> Result<()> {
+        let json = serde_json::to_string(&self.delivered_ahead)
+            .context("Failed to serialize delivered operations")?;
+        std::fs::write(&self.delivered_ahead_path, json).with_context(|| {
+            format!(
+                "Failed to write delivered operations: {}",
+                self.delivered_ahead_path.display()
+            )
+        })
+    }
+
+    /// Create a sync protocol which exchanges the operations in this store.
+    pub fn sync_protocol(&self) -> ChatSyncProtocol {
+        let topic_map = ChatTopicMap {
+            store: self.store.clone(),
+        };
+        LogSyncProtocol::new(topic_map, self.store.clone())
+    }
+
+    /// Encrypt a message with the room key, sign it as the next operation in our log for the
+    /// chat group and store it.
+    pub async fn publish(
+        &mut self,
+        private_key: &PrivateKey,
+        chat_group: &ChatGroup,
+        room_key: &RoomKey,
+        message: &NetworkMessage,
+    ) -> Result<RawOperation> {
+        let public_key = private_key.public_key();
+        let (seq_num, backlink) = match self.store.latest_operation(&public_key, chat_group).await?
+        {
+            Some((latest, _)) => (latest.seq_num + 1, Some(latest.hash())),
+            None => (0, None),
+        };
+
+        let sealed = room_key.seal(&serde_json::to_vec(message)?, chat_group.hash().as_bytes())?;
+        let body = Body::new(&sealed);
+        let timestamp = message.timestamp.duration_since(UNIX_EPOCH)?.as_micros() as u64;
+
+        let mut header = Header {
+            version: 1,
+            public_key,
+            signature: None,
+            payload_size: body.size(),
+            payload_hash: Some(body.hash()),
+            timestamp,
+            seq_num,
+            backlink,
+            previous: vec![],
+            extensions: None,
+        };
+        header.sign(private_key);
+
+        let header_bytes = header.to_bytes();
+        self.store
+            .insert_operation(
+                header.hash(),
+                &header,
+                Some(&body),
+                &header_bytes,
+                chat_group,
+            )
+            .await?;
+
+        Ok((header_bytes, Some(body.to_bytes())))
+    }
+
+    /// Validate and store an operation received from a peer.
+    ///
+    /// Returns the contained message if it has not been seen before, and an error if the
+    /// operation is malformed. Operations whose signature or author can't be verified fail with
+    /// an [`AuthenticationError`], payloads which can't be opened with the room key with a
+    /// [`DecryptionError`](crate::p2p::encryption::DecryptionError).
+    pub async fn ingest(
+        &mut self,
+        chat_group: &ChatGroup,
+        room_key: &RoomKey,
+        header_bytes: &[u8],
+        payload: Option<&[u8]>,
+    ) -> Result<Option<NetworkMessage>> {
+        let header = Header::try_from(header_bytes).context("Failed to decode header")?;
+        let payload = payload.context("Operation has no payload")?;
+        let operation = Operation {
+            hash: header.hash(),
+            header,
+            body: Some(Body::new(payload)),
+        };
+        if !operation.header.verify() {
+            return Err(AuthenticationError::SignatureMismatch.into());
+        }
+        validate_operation(&operation)?;
+
+        let plaintext = room_key.open(payload, chat_group.hash().as_bytes())?;
+        let message: NetworkMessage = serde_json::from_slice(&plaintext)?;
+        if message.author != Some(operation.header.public_key) {
+            return Err(AuthenticationError::AuthorMismatch.into());
+        }
+
+        if self.store.has_operation(operation.hash).await? {
+            return Ok(None);
+        }
+
+        let latest = self
+            .store
+            .latest_operation(&operation.header.public_key, chat_group)
+            .await?;
+        let next_seq_num = latest
+            .as_ref()
+            .map(|(latest, _)| latest.seq_num + 1)
+            .unwrap_or(0);
+
+        if operation.header.seq_num > next_seq_num {
+            // We missed earlier operations. Show this one now and store it once sync catches up
+            // on the rest of the log, otherwise our log height would skip the missing entries.
+            if self.delivered_ahead.contains_key(&operation.hash) {
+                return Ok(None);
+            }
+            self.delivered_ahead.insert(
+                operation.hash,
+                LogPosition {
+                    public_key: operation.header.public_key,
+                    chat_group: chat_group.clone(),
+                    seq_num: operation.header.seq_num,
+                },
+            );
+            self.save_delivered_ahead()?;
+            return Ok(Some(message));
+        }
+
+        if operation.header.seq_num < next_seq_num {
+            tracing::warn!(
+                "Ignoring forked operation {} at seq {}",
+                operation.hash,
+                operation.header.seq_num
+            );
+            return Ok(None);
+        }
+
+        if let Some((latest, _)) = &latest {
+            validate_backlink(latest, &operation.header)?;
+        }
+
+        self.store
+            .insert_operation(
+                operation.hash,
+                &operation.header,
+                operation.body.as_ref(),
+                header_bytes,
+                chat_group,
+            )
+            .await?;
+
+        // Forget what was delivered ahead of the log as it now stands, including this operation
+        let was_delivered = self.delivered_ahead.contains_key(&operation.hash);
+        let ahead = self.delivered_ahead.len();
+        self.delivered_ahead.retain(|_, position| {
+            position.public_key != operation.header.public_key
+                || &position.chat_group != chat_group
+                || position.seq_num > operation.header.seq_num
+        });
+        if self.delivered_ahead.len() < ahead {
+            self.save_delivered_ahead()?;
+        }
+        if was_delivered {
+            return Ok(None);
+        }
+
+        Ok(Some(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn chat_group() -> ChatGroup {
+        ChatGroup::from_hash(Hash::new("test-room".as_bytes()))
+    }
+
+    fn authored_message(content: &str, private_key: &PrivateKey) -> NetworkMessage {
+        let mut message = NetworkMessage::new(content.to_string(), "Alice".to_string());
+        message.author = Some(private_key.public_key());
+        message
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_ingest() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut alice = ChatOperations::open(&temp_dir.path().join("alice.sqlite")).await?;
+        let mut bob = ChatOperations::open(&temp_dir.path().join("bob.sqlite")).await?;
+        let private_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        let message = authored_message("Hello", &private_key);
+        let (header, payload) = alice.publish(&private_key, &group, &key, &message).await?;
+
+        let received = bob
+            .ingest(&group, &key, &header, payload.as_deref())
+            .await?;
+        assert_eq!(received.map(|m| m.content), Some("Hello".to_string()));
+
+        // The same operation delivered again, e.g. by sync, is not shown twice
+        let duplicate = bob
+            .ingest(&group, &key, &header, payload.as_deref())
+            .await?;
+        assert!(duplicate.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_tampered_payload() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut alice = ChatOperations::open(&temp_dir.path().join("alice.sqlite")).await?;
+        let mut bob = ChatOperations::open(&temp_dir.path().join("bob.sqlite")).await?;
+        let private_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        let message = authored_message("Hello", &private_key);
+        let (header, _) = alice.publish(&private_key, &group, &key, &message).await?;
+
+        let forged = authored_message("Goodbye", &private_key);
+        let forged_payload = key.seal(&serde_json::to_vec(&forged)?, group.hash().as_bytes())?;
+        assert!(
+            bob.ingest(&group, &key, &header, Some(&forged_payload))
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_wrong_room_key() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut alice = ChatOperations::open(&temp_dir.path().join("alice.sqlite")).await?;
+        let mut bob = ChatOperations::open(&temp_dir.path().join("bob.sqlite")).await?;
+        let private_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        let message = authored_message("Hello", &private_key);
+        let (header, payload) = alice.publish(&private_key, &group, &key, &message).await?;
+
+        // The payload on the wire is not readable without the room key
+        let payload = payload.unwrap();
+        assert!(serde_json::from_slice::<NetworkMessage>(&payload).is_err());
+
+        let err = bob
+            .ingest(&group, &RoomKey::generate(), &header, Some(&payload))
+            .await
+            .unwrap_err();
+        assert!(err.is::<crate::p2p::encryption::DecryptionError>());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_impersonation() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut mallory = ChatOperations::open(&temp_dir.path().join("mallory.sqlite")).await?;
+        let mut bob = ChatOperations::open(&temp_dir.path().join("bob.sqlite")).await?;
+        let alice_key = PrivateKey::new();
+        let mallory_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        // Mallory signs a message which claims to be written by Alice
+        let claimed = authored_message("I am Alice", &alice_key);
+        let (header, payload) = mallory
+            .publish(&mallory_key, &group, &key, &claimed)
+            .await?;
+
+        let err = bob
+            .ingest(&group, &key, &header, payload.as_deref())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<AuthenticationError>(),
+            Some(&AuthenticationError::AuthorMismatch)
+        );
+
+        // A header re-signed by someone else no longer matches its claimed public key
+        let mut forged = Header::try_from(header.as_slice())?;
+        forged.public_key = alice_key.public_key();
+        let err = bob
+            .ingest(&group, &key, &forged.to_bytes(), payload.as_deref())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<AuthenticationError>(),
+            Some(&AuthenticationError::SignatureMismatch)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ingest_out_of_order() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut alice = ChatOperations::open(&temp_dir.path().join("alice.sqlite")).await?;
+        let mut bob = ChatOperations::open(&temp_dir.path().join("bob.sqlite")).await?;
+        let private_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        let first = authored_message("First", &private_key);
+        let second = authored_message("Second", &private_key);
+        let (header_0, payload_0) = alice.publish(&private_key, &group, &key, &first).await?;
+        let (header_1, payload_1) = alice.publish(&private_key, &group, &key, &second).await?;
+
+        // The second message arrives first via gossip and is shown right away
+        let received = bob
+            .ingest(&group, &key, &header_1, payload_1.as_deref())
+            .await?;
+        assert_eq!(received.map(|m| m.content), Some("Second".to_string()));
+
+        // Sync then delivers the whole log in order, only the missing message is new
+        let received = bob
+            .ingest(&group, &key, &header_0, payload_0.as_deref())
+            .await?;
+        assert_eq!(received.map(|m| m.content), Some("First".to_string()));
+        let received = bob
+            .ingest(&group, &key, &header_1, payload_1.as_deref())
+            .await?;
+        assert!(received.is_none());
+
+        let heights = bob.store.get_log_heights(&group).await?;
+        assert_eq!(heights, vec![(private_key.public_key(), 1)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ingest_out_of_order_across_restart() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let bob_path = temp_dir.path().join("bob.sqlite");
+        let mut alice = ChatOperations::open(&temp_dir.path().join("alice.sqlite")).await?;
+        let mut bob = ChatOperations::open(&bob_path).await?;
+        let private_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        let first = authored_message("First", &private_key);
+        let second = authored_message("Second", &private_key);
+        let (header_0, payload_0) = alice.publish(&private_key, &group, &key, &first).await?;
+        let (header_1, payload_1) = alice.publish(&private_key, &group, &key, &second).await?;
+
+        let received = bob
+            .ingest(&group, &key, &header_1, payload_1.as_deref())
+            .await?;
+        assert!(received.is_some());
+
+        // After a restart, sync filling the gap doesn't show the second message again
+        drop(bob);
+        let mut bob = ChatOperations::open(&bob_path).await?;
+        let received = bob
+            .ingest(&group, &key, &header_0, payload_0.as_deref())
+            .await?;
+        assert_eq!(received.map(|m| m.content), Some("First".to_string()));
+        let received = bob
+            .ingest(&group, &key, &header_1, payload_1.as_deref())
+            .await?;
+        assert!(received.is_none());
+
+        // Nothing is remembered once the log is complete
+        assert!(bob.delivered_ahead.is_empty());
+        let saved = std::fs::read_to_string(bob_path.with_extension("delivered_ahead.json"))?;
+        assert_eq!(saved, "{}");
+
+        Ok(())
+    }
+}
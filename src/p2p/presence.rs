@@ -0,0 +1,135 @@
+use anyhow::Result;
+use p2panda_core::{PrivateKey, PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use super::operations::AuthenticationError;
+use crate::p2p::{ChatGroup, RoomKey};
+
+/// A peer telling a room who they are and which language they read.
+///
+/// Announcements are sent when joining a room and answered by peers who haven't heard from the
+/// announcer yet. They are repeated periodically as heartbeats, so peers notice when someone
+/// leaves. Unlike chat messages they are never stored or synced.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PeerAnnouncement {
+    pub public_key: PublicKey,
+    /// Display name chosen by the peer
+    pub display_name: String,
+    /// Language the peer reads messages in
+    pub language: String,
+    /// Whether the peer translates received messages itself. Senders attach translations for
+    /// peers who don't.
+    pub translates_locally: bool,
+    pub timestamp: SystemTime,
+}
+
+impl PeerAnnouncement {
+    pub fn new(
+        public_key: PublicKey,
+        display_name: String,
+        language: String,
+        translates_locally: bool,
+    ) -> Self {
+        Self {
+            public_key,
+            display_name,
+            language,
+            translates_locally,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// Encrypt the announcement with the room key and sign it with the announcer's key.
+    pub fn seal(
+        &self,
+        private_key: &PrivateKey,
+        chat_group: &ChatGroup,
+        room_key: &RoomKey,
+    ) -> Result<SealedAnnouncement> {
+        let payload = room_key.seal(&serde_json::to_vec(self)?, chat_group.hash().as_bytes())?;
+        let signature = private_key.sign(&payload);
+        Ok(SealedAnnouncement { payload, signature })
+    }
+}
+
+/// A [`PeerAnnouncement`] as sent over the network, only readable with the room key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealedAnnouncement {
+    payload: Vec<u8>,
+    signature: Signature,
+}
+
+impl SealedAnnouncement {
+    /// Decrypt the announcement and check it was signed by the key it announces.
+    ///
+    /// Fails with a [`DecryptionError`](crate::p2p::encryption::DecryptionError) if the payload
+    /// can't be opened with the room key, and with an [`AuthenticationError`] if the signature
+    /// doesn't match.
+    pub fn open(&self, chat_group: &ChatGroup, room_key: &RoomKey) -> Result<PeerAnnouncement> {
+        let plaintext = room_key.open(&self.payload, chat_group.hash().as_bytes())?;
+        let announcement: PeerAnnouncement = serde_json::from_slice(&plaintext)?;
+        if !announcement
+            .public_key
+            .verify(&self.payload, &self.signature)
+        {
+            return Err(AuthenticationError::SignatureMismatch.into());
+        }
+
+        Ok(announcement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p2panda_core::Hash;
+
+    fn chat_group() -> ChatGroup {
+        ChatGroup::from_hash(Hash::new("test-room".as_bytes()))
+    }
+
+    fn announcement(private_key: &PrivateKey) -> PeerAnnouncement {
+        PeerAnnouncement::new(
+            private_key.public_key(),
+            "Alice".to_string(),
+            "French".to_string(),
+            false,
+        )
+    }
+
+    #[test]
+    fn test_seal_and_open() -> Result<()> {
+        let private_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        let announcement = announcement(&private_key);
+        let sealed = announcement.seal(&private_key, &group, &key)?;
+        assert_eq!(sealed.open(&group, &key)?, announcement);
+
+        // Only readable with the room key
+        let err = sealed.open(&group, &RoomKey::generate()).unwrap_err();
+        assert!(err.is::<crate::p2p::encryption::DecryptionError>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_rejects_impersonation() -> Result<()> {
+        let alice_key = PrivateKey::new();
+        let mallory_key = PrivateKey::new();
+        let group = chat_group();
+        let key = RoomKey::generate();
+
+        // Mallory announces Alice's key but can only sign with their own
+        let sealed = announcement(&alice_key).seal(&mallory_key, &group, &key)?;
+        let err = sealed.open(&group, &key).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<AuthenticationError>(),
+            Some(&AuthenticationError::SignatureMismatch)
+        );
+
+        Ok(())
+    }
+}
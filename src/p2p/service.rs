@@ -1,9 +1,10 @@
 use anyhow::Result;
 
 use super::task::network_background_task;
-use crate::p2p::{ChatGroup, NetworkCommand, NetworkEvent, NetworkMessage};
+use crate::config::Config;
+use crate::p2p::{ChatGroup, NetworkCommand, NetworkEvent, NetworkMessage, RoomKey};
 
-/// Handles network communication for a specific chat group using background tasks.
+/// Handles network communication for all joined chat groups using a background task.
 #[derive(Debug)]
 pub struct ChatNetworkService {
     /// Send commands to the background network task
@@ -21,7 +22,10 @@ impl ChatNetworkService {
     }
 
     /// Initialize the network service with command/event channels
-    pub fn initialize_channels(&mut self) -> tokio::sync::mpsc::UnboundedSender<NetworkCommand> {
+    pub fn initialize_channels(
+        &mut self,
+        config: &Config,
+    ) -> tokio::sync::mpsc::UnboundedSender<NetworkCommand> {
         let (command_tx, command_rx) = tokio::sync::mpsc::unbounded_channel();
         let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
 
@@ -29,29 +33,42 @@ impl ChatNetworkService {
         self.event_rx = Some(event_rx);
 
         // Spawn the background network task
-        tokio::spawn(network_background_task(command_rx, event_tx));
+        tokio::spawn(network_background_task(
+            config.clone(),
+            command_rx,
+            event_tx,
+        ));
 
         command_tx
     }
 
-    /// Send a message to the network via the background task
-    pub fn send_message(&self, message: NetworkMessage) -> Result<()> {
+    /// Send a message to a chat group via the background task
+    pub fn send_message(&self, chat_group: ChatGroup, message: NetworkMessage) -> Result<()> {
         if let Some(tx) = &self.command_tx {
-            tx.send(NetworkCommand::SendMessage(message))
+            tx.send(NetworkCommand::SendMessage(chat_group, Box::new(message)))
                 .map_err(|e| anyhow::anyhow!("Failed to send network command: {}", e))?;
         }
         Ok(())
     }
 
     /// Subscribe to a chat group via the background task
-    pub fn subscribe(&self, chat_group: ChatGroup) -> Result<()> {
+    pub fn subscribe(&self, chat_group: ChatGroup, room_key: RoomKey) -> Result<()> {
         if let Some(tx) = &self.command_tx {
-            tx.send(NetworkCommand::Subscribe(chat_group))
+            tx.send(NetworkCommand::Subscribe(chat_group, room_key))
                 .map_err(|e| anyhow::anyhow!("Failed to send subscribe command: {}", e))?;
         }
         Ok(())
     }
 
+    /// Unsubscribe from a chat group via the background task
+    pub fn unsubscribe(&self, chat_group: ChatGroup) -> Result<()> {
+        if let Some(tx) = &self.command_tx {
+            tx.send(NetworkCommand::Unsubscribe(chat_group))
+                .map_err(|e| anyhow::anyhow!("Failed to send unsubscribe command: {}", e))?;
+        }
+        Ok(())
+    }
+
     /// Try to receive a network event (non-blocking)
     pub fn try_receive_event(&mut self) -> Result<Option<NetworkEvent>> {
         if let Some(rx) = &mut self.event_rx {
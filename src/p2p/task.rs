@@ -1,28 +1,80 @@
+use anyhow::Result;
+use p2panda_core::cbor::{decode_cbor, encode_cbor};
+use p2panda_core::{PrivateKey, PublicKey};
 use p2panda_net::{FromNetwork, Network, ToNetwork};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
+use super::encryption::DecryptionError;
 use super::network::create_network;
-use crate::p2p::{ChatGroup, NetworkCommand, NetworkError, NetworkEvent, NetworkMessage};
+use super::operations::{AuthenticationError, ChatOperations};
+use super::presence::SealedAnnouncement;
+use crate::config::Config;
+use crate::identity::Identity;
+use crate::p2p::{
+    ChatGroup, GossipMessage, NetworkCommand, NetworkError, NetworkEvent, NetworkMessage,
+    PeerAnnouncement, RoomKey,
+};
+
+/// How often we repeat our announcement in every chat group, so peers know we're still there
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Peers we haven't heard from for this long are considered to have left
+const PEER_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Messages from the subscriptions of all chat groups, tagged with the group they belong to
+type IncomingMessage = (ChatGroup, Option<FromNetwork>);
+
+/// An active subscription to a chat group
+struct RoomSubscription {
+    /// Key the chat group's messages are encrypted with
+    room_key: RoomKey,
+    tx: tokio::sync::mpsc::Sender<ToNetwork>,
+    /// Forwards messages of this subscription into the shared incoming channel
+    forwarder: tokio::task::JoinHandle<()>,
+    /// Peers present in this chat group and when we last heard from them
+    known_peers: HashMap<PublicKey, Instant>,
+}
+
+impl Drop for RoomSubscription {
+    fn drop(&mut self) {
+        self.forwarder.abort();
+    }
+}
 
 /// State for the network background task
 struct NetworkTaskState {
     network: Network<ChatGroup>,
-    current_subscription: Option<(
-        tokio::sync::mpsc::Sender<ToNetwork>,
-        tokio::sync::mpsc::Receiver<FromNetwork>,
-    )>,
-    subscription_ready: Option<tokio::sync::oneshot::Receiver<()>>,
+    private_key: PrivateKey,
+    operations: ChatOperations,
+    /// Display name and language we announce to the chat groups we join
+    display_name: String,
+    language: String,
+    translates_locally: bool,
+    /// Chat groups we're subscribed to
+    rooms: HashMap<ChatGroup, RoomSubscription>,
+    incoming_tx: tokio::sync::mpsc::UnboundedSender<IncomingMessage>,
     event_tx: tokio::sync::mpsc::UnboundedSender<NetworkEvent>,
 }
 
 impl NetworkTaskState {
-    pub async fn new(event_tx: tokio::sync::mpsc::UnboundedSender<NetworkEvent>) -> Option<Self> {
-        match create_network().await {
-            Ok(network) => {
+    pub async fn new(
+        config: &Config,
+        incoming_tx: tokio::sync::mpsc::UnboundedSender<IncomingMessage>,
+        event_tx: tokio::sync::mpsc::UnboundedSender<NetworkEvent>,
+    ) -> Option<Self> {
+        match Self::create(config).await {
+            Ok((network, private_key, operations)) => {
                 tracing::info!("Network created successfully in background task");
                 Some(Self {
                     network,
-                    current_subscription: None,
-                    subscription_ready: None,
+                    private_key,
+                    operations,
+                    display_name: config.username.clone(),
+                    language: config.target_language.clone(),
+                    translates_locally: !config.disable_ai,
+                    rooms: HashMap::new(),
+                    incoming_tx,
                     event_tx,
                 })
             }
@@ -36,81 +88,182 @@ impl NetworkTaskState {
         }
     }
 
+    async fn create(config: &Config) -> Result<(Network<ChatGroup>, PrivateKey, ChatOperations)> {
+        let operations =
+            ChatOperations::open(&config.data_dir()?.join("operations.sqlite")).await?;
+
+        // The persistent Ed25519 identity authenticates your peer towards others and signs chat
+        // operations.
+        let identity = Identity::load_or_create(&config.identity_path()?)?;
+        let private_key = identity.private_key().clone();
+
+        let network = create_network(config, private_key.clone(), &operations).await?;
+        Ok((network, private_key, operations))
+    }
+
+    /// Our announcement for a chat group, ready to be sent on its topic
+    fn announcement_bytes(&self, chat_group: &ChatGroup, room_key: &RoomKey) -> Result<Vec<u8>> {
+        let announcement = PeerAnnouncement::new(
+            self.private_key.public_key(),
+            self.display_name.clone(),
+            self.language.clone(),
+            self.translates_locally,
+        );
+        let sealed = announcement.seal(&self.private_key, chat_group, room_key)?;
+        Ok(encode_cbor(&GossipMessage::Announcement(sealed))?)
+    }
+
     pub async fn handle_command(&mut self, command: NetworkCommand) {
         match command {
-            NetworkCommand::Subscribe(chat_group) => {
-                self.handle_subscribe_command(chat_group).await;
+            NetworkCommand::Subscribe(chat_group, room_key) => {
+                self.handle_subscribe_command(chat_group, room_key).await;
             }
-            NetworkCommand::SendMessage(message) => {
-                self.handle_send_message_command(message).await;
+            NetworkCommand::SendMessage(chat_group, message) => {
+                self.handle_send_message_command(chat_group, *message).await;
             }
-            NetworkCommand::Unsubscribe => {
-                self.handle_unsubscribe_command().await;
+            NetworkCommand::Unsubscribe(chat_group) => {
+                self.handle_unsubscribe_command(chat_group).await;
             }
         }
     }
 
-    async fn handle_subscribe_command(&mut self, chat_group: ChatGroup) {
+    async fn handle_subscribe_command(&mut self, chat_group: ChatGroup, room_key: RoomKey) {
         tracing::info!("Background task: subscribing to {:?}", chat_group);
 
+        if self.rooms.contains_key(&chat_group) {
+            tracing::debug!("Already subscribed to chat group");
+            let _ = self.event_tx.send(NetworkEvent::Subscribed(chat_group));
+            return;
+        }
+
         match self.network.subscribe(chat_group.clone()).await {
-            Ok((tx, rx, ready)) => {
+            Ok((tx, mut rx, ready)) => {
                 tracing::info!("Successfully subscribed to chat group");
-                self.current_subscription = Some((tx, rx));
-                self.subscription_ready = Some(ready);
+
+                // Every room keeps receiving in the background, so merge all subscriptions into
+                // one channel the task loop can wait on
+                // Announce ourselves once we joined the gossip overlay, before that nobody hears it
+                match self.announcement_bytes(&chat_group, &room_key) {
+                    Ok(bytes) => {
+                        let tx = tx.clone();
+                        tokio::spawn(async move {
+                            if ready.await.is_ok() {
+                                let _ = tx.send(ToNetwork::Message { bytes }).await;
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to create announcement: {}", e);
+                    }
+                }
+
+                let incoming_tx = self.incoming_tx.clone();
+                let group = chat_group.clone();
+                let forwarder = tokio::spawn(async move {
+                    loop {
+                        let from_network = rx.recv().await;
+                        let closed = from_network.is_none();
+                        if incoming_tx.send((group.clone(), from_network)).is_err() || closed {
+                            break;
+                        }
+                    }
+                });
+
+                self.rooms.insert(
+                    chat_group.clone(),
+                    RoomSubscription {
+                        room_key,
+                        tx,
+                        forwarder,
+                        known_peers: HashMap::new(),
+                    },
+                );
                 let _ = self.event_tx.send(NetworkEvent::Subscribed(chat_group));
             }
             Err(e) => {
                 tracing::error!("Failed to subscribe to chat group: {}", e);
-                let _ = self
-                    .event_tx
-                    .send(NetworkEvent::Error(NetworkError::SubscriptionFailed(
-                        e.to_string(),
-                    )));
+                let _ = self.event_tx.send(NetworkEvent::RoomError(
+                    chat_group,
+                    NetworkError::SubscriptionFailed(e.to_string()),
+                ));
             }
         }
     }
 
-    async fn handle_send_message_command(&mut self, message: NetworkMessage) {
+    async fn handle_send_message_command(
+        &mut self,
+        chat_group: ChatGroup,
+        mut message: NetworkMessage,
+    ) {
         tracing::info!("Background task: sending message {:?}", message);
 
-        if let Some((tx, _)) = &self.current_subscription {
-            match serde_json::to_vec(&message) {
-                Ok(serialized) => {
-                    let to_network = ToNetwork::Message { bytes: serialized };
-                    if let Err(e) = tx.send(to_network).await {
-                        tracing::error!("Failed to send message: {}", e);
-                        let _ = self
-                            .event_tx
-                            .send(NetworkEvent::Error(NetworkError::SendFailed(e.to_string())));
-                    } else {
-                        tracing::info!("Message sent successfully");
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("Failed to serialize message: {}", e);
-                    let _ =
-                        self.event_tx
-                            .send(NetworkEvent::Error(NetworkError::SerializationFailed(
-                                e.to_string(),
-                            )));
+        // Attribute the message to our key, receivers check it against the operation signature
+        message.author = Some(self.private_key.public_key());
+
+        let Some(room) = self.rooms.get(&chat_group) else {
+            tracing::warn!("No active subscription to send message");
+            let _ = self.event_tx.send(NetworkEvent::RoomError(
+                chat_group,
+                NetworkError::ChannelClosed,
+            ));
+            return;
+        };
+
+        // Append the message to our log first, so peers can sync it even if gossip misses them
+        let operation = match self
+            .operations
+            .publish(&self.private_key, &chat_group, &room.room_key, &message)
+            .await
+        {
+            Ok(operation) => operation,
+            Err(e) => {
+                tracing::error!("Failed to create operation: {}", e);
+                let _ = self.event_tx.send(NetworkEvent::RoomError(
+                    chat_group,
+                    NetworkError::SerializationFailed(e.to_string()),
+                ));
+                return;
+            }
+        };
+
+        match encode_cbor(&GossipMessage::Operation(operation)) {
+            Ok(serialized) => {
+                let to_network = ToNetwork::Message { bytes: serialized };
+                if let Err(e) = room.tx.send(to_network).await {
+                    tracing::error!("Failed to send message: {}", e);
+                    let _ = self.event_tx.send(NetworkEvent::RoomError(
+                        chat_group,
+                        NetworkError::SendFailed(e.to_string()),
+                    ));
+                } else {
+                    tracing::info!("Message sent successfully");
                 }
             }
-        } else {
-            tracing::warn!("No active subscription to send message");
-            let _ = self
-                .event_tx
-                .send(NetworkEvent::Error(NetworkError::ChannelClosed));
+            Err(e) => {
+                tracing::error!("Failed to serialize message: {}", e);
+                let _ = self.event_tx.send(NetworkEvent::RoomError(
+                    chat_group,
+                    NetworkError::SerializationFailed(e.to_string()),
+                ));
+            }
         }
     }
 
-    async fn handle_unsubscribe_command(&mut self) {
-        tracing::info!("Background task: unsubscribing");
-        self.current_subscription = None;
-        self.subscription_ready = None;
+    async fn handle_unsubscribe_command(&mut self, chat_group: ChatGroup) {
+        tracing::info!("Background task: unsubscribing from {:?}", chat_group);
+        self.rooms.remove(&chat_group);
     }
 
-    pub fn handle_network_message(&mut self, from_network: Option<FromNetwork>) {
+    pub async fn handle_network_message(
+        &mut self,
+        chat_group: ChatGroup,
+        from_network: Option<FromNetwork>,
+    ) {
+        // Messages still in flight for a chat group we left
+        if !self.rooms.contains_key(&chat_group) {
+            return;
+        }
+
         match from_network {
             Some(FromNetwork::GossipMessage {
                 bytes,
@@ -121,48 +274,176 @@ impl NetworkTaskState {
                     bytes.len(),
                     delivered_from
                 );
-                match serde_json::from_slice::<NetworkMessage>(&bytes) {
-                    Ok(network_message) => {
-                        tracing::info!("Parsed network message: {:?}", network_message);
-                        let _ = self
-                            .event_tx
-                            .send(NetworkEvent::MessageReceived(network_message));
+                match decode_cbor::<GossipMessage, _>(&bytes[..]) {
+                    Ok(GossipMessage::Operation((header, payload))) => {
+                        self.handle_operation(chat_group, &header, payload.as_deref())
+                            .await;
+                    }
+                    Ok(GossipMessage::Announcement(sealed)) => {
+                        self.handle_announcement(chat_group, sealed).await;
                     }
                     Err(e) => {
                         tracing::error!("Failed to parse network message: {}", e);
-                        let _ = self.event_tx.send(NetworkEvent::Error(
+                        let _ = self.event_tx.send(NetworkEvent::RoomError(
+                            chat_group,
                             NetworkError::SerializationFailed(e.to_string()),
                         ));
                     }
                 }
             }
             Some(FromNetwork::SyncMessage {
-                header: _,
+                header,
                 payload,
                 delivered_from,
             }) => {
                 tracing::debug!("Received sync message from {:?}", delivered_from);
-                if let Some(bytes) = payload {
-                    match serde_json::from_slice::<NetworkMessage>(&bytes) {
-                        Ok(network_message) => {
-                            tracing::info!("Parsed sync message: {:?}", network_message);
-                            let _ = self
-                                .event_tx
-                                .send(NetworkEvent::MessageReceived(network_message));
-                        }
-                        Err(e) => {
-                            tracing::debug!("Sync message payload is not a chat message: {}", e);
-                        }
+                self.handle_operation(chat_group, &header, payload.as_deref())
+                    .await;
+            }
+            None => {
+                tracing::warn!("Network message channel closed for {:?}", chat_group);
+                self.rooms.remove(&chat_group);
+                let _ = self.event_tx.send(NetworkEvent::RoomError(
+                    chat_group,
+                    NetworkError::SubscriptionLost,
+                ));
+            }
+        }
+    }
+
+    /// Verify a peer's announcement and forward it. Peers we haven't heard from before joined
+    /// the chat group and get our own announcement in return, so newcomers learn who is
+    /// already there.
+    async fn handle_announcement(&mut self, chat_group: ChatGroup, sealed: SealedAnnouncement) {
+        let own_public_key = self.private_key.public_key();
+        let Some(room) = self.rooms.get_mut(&chat_group) else {
+            return;
+        };
+
+        let announcement = match sealed.open(&chat_group, &room.room_key) {
+            Ok(announcement) => announcement,
+            Err(e) => {
+                tracing::warn!("Dropping invalid announcement: {}", e);
+                let error = if e.is::<AuthenticationError>() {
+                    NetworkError::InvalidSignature(e.to_string())
+                } else if e.is::<DecryptionError>() {
+                    NetworkError::DecryptionFailed(e.to_string())
+                } else {
+                    NetworkError::InvalidOperation(e.to_string())
+                };
+                let _ = self
+                    .event_tx
+                    .send(NetworkEvent::RoomError(chat_group, error));
+                return;
+            }
+        };
+
+        // Our own announcement echoed back to us
+        if announcement.public_key == own_public_key {
+            return;
+        }
+
+        let is_new = room
+            .known_peers
+            .insert(announcement.public_key, Instant::now())
+            .is_none();
+        if !is_new {
+            let _ = self.event_tx.send(NetworkEvent::PeerAnnounced(
+                chat_group,
+                Box::new(announcement),
+            ));
+            return;
+        }
+
+        tracing::info!("Peer joined: {:?}", announcement);
+        let tx = room.tx.clone();
+        let room_key = room.room_key.clone();
+        match self.announcement_bytes(&chat_group, &room_key) {
+            Ok(bytes) => {
+                if let Err(e) = tx.send(ToNetwork::Message { bytes }).await {
+                    tracing::warn!("Failed to answer announcement: {}", e);
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to create announcement: {}", e);
+            }
+        }
+
+        let _ = self
+            .event_tx
+            .send(NetworkEvent::PeerJoined(chat_group, Box::new(announcement)));
+    }
+
+    /// Repeat our announcement in every chat group and let go of peers who went silent.
+    async fn handle_heartbeat(&mut self) {
+        let mut heartbeats = Vec::new();
+        for (chat_group, room) in &mut self.rooms {
+            heartbeats.push((chat_group.clone(), room.tx.clone(), room.room_key.clone()));
+
+            room.known_peers.retain(|public_key, last_seen| {
+                let present = last_seen.elapsed() < PEER_TIMEOUT;
+                if !present {
+                    tracing::info!("Peer left: {}", public_key);
+                    let _ = self
+                        .event_tx
+                        .send(NetworkEvent::PeerLeft(chat_group.clone(), *public_key));
+                }
+                present
+            });
+        }
+
+        for (chat_group, tx, room_key) in heartbeats {
+            match self.announcement_bytes(&chat_group, &room_key) {
+                Ok(bytes) => {
+                    if let Err(e) = tx.send(ToNetwork::Message { bytes }).await {
+                        tracing::warn!("Failed to send heartbeat: {}", e);
                     }
                 }
+                Err(e) => {
+                    tracing::error!("Failed to create announcement: {}", e);
+                }
             }
-            None => {
-                tracing::warn!("Network message channel closed");
-                self.current_subscription = None;
-                self.subscription_ready = None;
+        }
+    }
+
+    /// Validate and store an operation from a peer, forwarding its message if it is new.
+    async fn handle_operation(
+        &mut self,
+        chat_group: ChatGroup,
+        header: &[u8],
+        payload: Option<&[u8]>,
+    ) {
+        let Some(room) = self.rooms.get(&chat_group) else {
+            return;
+        };
+
+        match self
+            .operations
+            .ingest(&chat_group, &room.room_key, header, payload)
+            .await
+        {
+            Ok(Some(network_message)) => {
+                tracing::info!("Parsed network message: {:?}", network_message);
+                let _ = self.event_tx.send(NetworkEvent::MessageReceived(
+                    chat_group,
+                    Box::new(network_message),
+                ));
+            }
+            Ok(None) => {
+                tracing::debug!("Ignoring already known operation");
+            }
+            Err(e) => {
+                tracing::warn!("Dropping invalid operation: {}", e);
+                let error = if e.is::<AuthenticationError>() {
+                    NetworkError::InvalidSignature(e.to_string())
+                } else if e.is::<DecryptionError>() {
+                    NetworkError::DecryptionFailed(e.to_string())
+                } else {
+                    NetworkError::InvalidOperation(e.to_string())
+                };
                 let _ = self
                     .event_tx
-                    .send(NetworkEvent::Error(NetworkError::SubscriptionLost));
+                    .send(NetworkEvent::RoomError(chat_group, error));
             }
         }
     }
@@ -170,18 +451,25 @@ impl NetworkTaskState {
 
 /// Background task that handles all network operations
 pub async fn network_background_task(
+    config: Config,
     mut command_rx: tokio::sync::mpsc::UnboundedReceiver<NetworkCommand>,
     event_tx: tokio::sync::mpsc::UnboundedSender<NetworkEvent>,
 ) {
     tracing::info!("Network background task started");
 
+    let (incoming_tx, mut incoming_rx) = tokio::sync::mpsc::unbounded_channel();
+
     // Initialize network task state
-    let mut state = match NetworkTaskState::new(event_tx).await {
+    let mut state = match NetworkTaskState::new(&config, incoming_tx, event_tx).await {
         Some(state) => state,
         // Error already sent via event_tx
         None => return,
     };
 
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    // The first tick completes immediately, rooms announce themselves once subscribed
+    heartbeat.tick().await;
+
     // Main task loop
     loop {
         tokio::select! {
@@ -198,16 +486,14 @@ pub async fn network_background_task(
                 }
             }
 
-            // Handle incoming network messages
-            from_network = async {
-                if let Some((_, rx)) = &mut state.current_subscription {
-                    rx.recv().await
-                } else {
-                    // If no subscription, just wait indefinitely
-                    std::future::pending().await
-                }
-            } => {
-                state.handle_network_message(from_network);
+            // Handle incoming network messages of all subscribed chat groups. The state holds a
+            // sender, so this channel never closes while the loop runs.
+            Some((chat_group, from_network)) = incoming_rx.recv() => {
+                state.handle_network_message(chat_group, from_network).await;
+            }
+
+            _ = heartbeat.tick() => {
+                state.handle_heartbeat().await;
             }
         }
     }
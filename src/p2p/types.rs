@@ -1,19 +1,30 @@
-use super::{ChatGroup, NetworkMessage};
+use p2panda_core::PublicKey;
+
+use super::{ChatGroup, NetworkMessage, PeerAnnouncement, RoomKey};
 
 /// Commands that can be sent to the background network task
 #[derive(Debug, Clone)]
 pub enum NetworkCommand {
-    Subscribe(ChatGroup),
-    SendMessage(NetworkMessage),
-    Unsubscribe,
+    Subscribe(ChatGroup, RoomKey),
+    SendMessage(ChatGroup, Box<NetworkMessage>),
+    Unsubscribe(ChatGroup),
 }
 
 /// Events that the background network task sends back to the UI
 #[derive(Debug, Clone)]
 pub enum NetworkEvent {
-    MessageReceived(NetworkMessage),
+    MessageReceived(ChatGroup, Box<NetworkMessage>),
+    /// An error affecting the whole network, e.g. it could not be created
     Error(NetworkError),
+    /// An error affecting a single chat group
+    RoomError(ChatGroup, NetworkError),
     Subscribed(ChatGroup),
+    /// A peer we haven't heard from before announced itself in a chat group
+    PeerJoined(ChatGroup, Box<PeerAnnouncement>),
+    /// A present peer repeated its announcement, as it does periodically
+    PeerAnnounced(ChatGroup, Box<PeerAnnouncement>),
+    /// A peer stopped sending heartbeats to a chat group
+    PeerLeft(ChatGroup, PublicKey),
 }
 
 /// Network error types
@@ -25,4 +36,7 @@ pub enum NetworkError {
     NetworkCreationFailed(String),
     SubscriptionFailed(String),
     SerializationFailed(String),
+    InvalidOperation(String),
+    InvalidSignature(String),
+    DecryptionFailed(String),
 }
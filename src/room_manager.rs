@@ -1,9 +1,13 @@
 use anyhow::Result;
 use p2panda_core::Hash;
 use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
-use crate::p2p::ChatGroup;
+use crate::p2p::{ChatGroup, RoomKey};
+
+/// Length of the hex encoded room key in an identifier
+const ROOM_KEY_HEX_LEN: usize = 64;
 
 /// Represents a chat room with its identifier and metadata
 #[derive(Debug, Clone)]
@@ -11,32 +15,36 @@ pub struct Room {
     pub hash: Hash,
     pub uuid: Uuid,
     pub name: String,
-    pub identifier: String, // Format: hash-uuid-name
+    /// Encrypts all messages in the room. Only peers who know the identifier can read them.
+    pub key: RoomKey,
+    pub identifier: String, // Format: hash-uuid-key-name
 }
 
 impl Room {
-    /// Create a new room from a name, generating a BLAKE3 hash and UUID
+    /// Create a new room from a name, generating a BLAKE3 hash, UUID and room key
     pub fn new(name: String) -> Self {
         let hash = Hash::new(name.as_bytes());
         let uuid = Uuid::new_v4();
-        let identifier = format!("{}-{}-{}", hash, uuid, name);
+        let key = RoomKey::generate();
+        let identifier = format!("{}-{}-{}-{}", hash, uuid, key.to_hex(), name);
 
         Self {
             hash,
             uuid,
             name,
+            key,
             identifier,
         }
     }
 
-    /// Parse a room identifier string (hash-uuid-name format)
+    /// Parse a room identifier string (hash-uuid-key-name format)
     pub fn from_identifier(identifier: String) -> Result<Self> {
         // Find the first dash (after hash)
         let first_dash = identifier.find('-').ok_or_else(|| {
-            anyhow::anyhow!("Invalid room identifier format. Expected: hash-uuid-name")
+            anyhow::anyhow!("Invalid room identifier format. Expected: hash-uuid-key-name")
         })?;
 
-        let _hash_str = &identifier[0..first_dash];
+        let hash_str = &identifier[0..first_dash];
         let remainder = &identifier[first_dash + 1..];
 
         // UUID is always 36 characters (with dashes), so we can extract it precisely
@@ -47,7 +55,14 @@ impl Room {
         }
 
         let uuid_str = &remainder[0..36];
-        let name_part = &remainder[36..];
+        let key_part = &remainder[36..];
+
+        // The room key is always 64 hex characters between dashes
+        let key_str = key_part
+            .strip_prefix('-')
+            .and_then(|key_part| key_part.get(0..ROOM_KEY_HEX_LEN))
+            .ok_or_else(|| anyhow::anyhow!("Invalid room identifier format. Missing room key"))?;
+        let name_part = &key_part[1 + ROOM_KEY_HEX_LEN..];
 
         // Remove leading dash from name if present
         let name = if name_part.starts_with('-') {
@@ -58,24 +73,44 @@ impl Room {
             ));
         };
 
+        let key = RoomKey::from_hex(key_str)
+            .map_err(|e| anyhow::anyhow!("Invalid room key in room identifier: {}", e))?;
+
         // Parse the UUID
         let uuid = Uuid::parse_str(uuid_str)
             .map_err(|e| anyhow::anyhow!("Invalid UUID in room identifier: {}", e))?;
 
-        // Reconstruct the hash from the name for validation
-        let hash = Hash::new(name.as_bytes());
+        // The embedded hash must match the name, otherwise the identifier was
+        // mistyped or tampered with
+        let hash = Hash::from_str(hash_str)
+            .map_err(|e| anyhow::anyhow!("Invalid hash in room identifier: {}", e))?;
+        if hash != Hash::new(name.as_bytes()) {
+            return Err(anyhow::anyhow!(
+                "Invalid room identifier. Hash does not match room name"
+            ));
+        }
 
         Ok(Self {
             hash,
             uuid,
             name,
+            key,
             identifier,
         })
     }
 
+    /// Derive the topic hash from the full room identity (name hash and UUID), so rooms
+    /// sharing a name do not share a gossip topic.
+    pub fn topic_hash(&self) -> Hash {
+        let mut buf = Vec::with_capacity(32 + 16);
+        buf.extend_from_slice(self.hash.as_bytes());
+        buf.extend_from_slice(self.uuid.as_bytes());
+        Hash::new(buf)
+    }
+
     /// Create a ChatGroup for p2p networking from this room.
     pub fn to_chat_group(&self) -> ChatGroup {
-        ChatGroup::from_hash(self.hash.clone())
+        ChatGroup::from_hash(self.topic_hash())
     }
 }
 
@@ -137,9 +172,9 @@ mod tests {
         assert_eq!(room.name, "my-test-room");
         assert!(room.identifier.contains("my-test-room"));
 
-        // Should have 2 dashes: hash-uuid-name
+        // Should have at least 3 dashes: hash-uuid-key-name
         let dash_count = room.identifier.chars().filter(|&c| c == '-').count();
-        assert!(dash_count >= 2);
+        assert!(dash_count >= 3);
     }
 
     #[test]
@@ -148,6 +183,21 @@ mod tests {
         let parsed = Room::from_identifier(original.identifier.clone()).unwrap();
         assert_eq!(parsed.name, original.name);
         assert_eq!(parsed.uuid, original.uuid);
+        assert_eq!(parsed.key, original.key);
+    }
+
+    #[test]
+    fn test_identifier_requires_room_key() {
+        let original = Room::new("test-room".to_string());
+        let key_hex = original.key.to_hex();
+
+        // Identifiers without a key (the old hash-uuid-name format) are rejected
+        let without_key = original.identifier.replace(&format!("{}-", key_hex), "");
+        assert!(Room::from_identifier(without_key).is_err());
+
+        // A mistyped key is rejected
+        let bad_key = original.identifier.replace(&key_hex, &"z".repeat(64));
+        assert!(Room::from_identifier(bad_key).is_err());
     }
 
     #[test]
@@ -158,4 +208,29 @@ mod tests {
         let result2 = Room::from_identifier("hash-only".to_string());
         assert!(result2.is_err());
     }
+
+    #[test]
+    fn test_tampered_identifier() {
+        let original = Room::new("test-room".to_string());
+
+        // Changing the name invalidates the embedded hash
+        let tampered = original.identifier.replace("test-room", "other-room");
+        assert!(Room::from_identifier(tampered).is_err());
+
+        // Garbage in the hash segment is rejected
+        let mistyped = format!("zz{}", &original.identifier[2..]);
+        assert!(Room::from_identifier(mistyped).is_err());
+    }
+
+    #[test]
+    fn test_same_name_different_chat_groups() {
+        let room1 = Room::new("standup".to_string());
+        let room2 = Room::new("standup".to_string());
+        assert_eq!(room1.hash, room2.hash);
+        assert_ne!(room1.to_chat_group(), room2.to_chat_group());
+
+        // A parsed identifier must map to the same topic as the original
+        let parsed = Room::from_identifier(room1.identifier.clone()).unwrap();
+        assert_eq!(parsed.to_chat_group(), room1.to_chat_group());
+    }
 }
@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::room_manager::Room;
+
+/// A room the user created or joined before.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedRoom {
+    /// Full room identifier, which is all that is needed to rejoin
+    pub identifier: String,
+    pub name: String,
+    pub last_visited: SystemTime,
+    /// Local name chosen by the user, never shared with peers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedRoomsFile {
+    #[serde(default)]
+    rooms: Vec<SavedRoom>,
+}
+
+/// Registry of rooms created and joined, stored as JSON in the data directory.
+#[derive(Debug)]
+pub struct SavedRooms {
+    path: PathBuf,
+    /// Ordered by last visit, most recent first
+    rooms: Vec<SavedRoom>,
+}
+
+impl SavedRooms {
+    /// Load the registry from the given data directory. A missing file is an empty registry.
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join("saved_rooms.json");
+
+        let rooms = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read saved rooms: {}", path.display()))?;
+            let file: SavedRoomsFile = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse saved rooms: {}", path.display()))?;
+            file.rooms
+        } else {
+            Vec::new()
+        };
+
+        let mut saved_rooms = Self { path, rooms };
+        saved_rooms.sort();
+        Ok(saved_rooms)
+    }
+
+    pub fn rooms(&self) -> &[SavedRoom] {
+        &self.rooms
+    }
+
+    pub fn get(&self, identifier: &str) -> Option<&SavedRoom> {
+        self.rooms.iter().find(|room| room.identifier == identifier)
+    }
+
+    /// Remember a room as visited now, adding it if it is new.
+    pub fn record_visit(&mut self, room: &Room) -> Result<()> {
+        let now = SystemTime::now();
+        match self
+            .rooms
+            .iter_mut()
+            .find(|saved| saved.identifier == room.identifier)
+        {
+            Some(saved) => saved.last_visited = now,
+            None => self.rooms.push(SavedRoom {
+                identifier: room.identifier.clone(),
+                name: room.name.clone(),
+                last_visited: now,
+                nickname: None,
+            }),
+        }
+
+        self.sort();
+        self.save()
+    }
+
+    /// Set or, with `None`, clear the local nickname of a room.
+    pub fn rename(&mut self, identifier: &str, nickname: Option<String>) -> Result<()> {
+        let Some(saved) = self
+            .rooms
+            .iter_mut()
+            .find(|saved| saved.identifier == identifier)
+        else {
+            return Ok(());
+        };
+
+        saved.nickname = nickname.filter(|nickname| !nickname.trim().is_empty());
+        self.save()
+    }
+
+    /// Remove a room from the registry. Its chat history is kept.
+    pub fn forget(&mut self, identifier: &str) -> Result<()> {
+        self.rooms.retain(|saved| saved.identifier != identifier);
+        self.save()
+    }
+
+    fn sort(&mut self) {
+        self.rooms
+            .sort_by_key(|saved| std::cmp::Reverse(saved.last_visited));
+    }
+
+    /// Write the registry to a file only readable by the current user, as the identifiers
+    /// contain the room keys
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create data directory: {}", parent.display())
+            })?;
+        }
+
+        let file = SavedRoomsFile {
+            rooms: self.rooms.clone(),
+        };
+        let content =
+            serde_json::to_string_pretty(&file).context("Failed to serialize saved rooms")?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options
+            .open(&self.path)
+            .with_context(|| format!("Failed to open saved rooms: {}", self.path.display()))?;
+
+        // `mode` only applies when the file is created, so tighten existing files as well
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+
+        file.write_all(content.as_bytes())
+            .with_context(|| format!("Failed to write saved rooms: {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_record_visit_round_trip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let standup = Room::new("standup".to_string());
+        let random = Room::new("random".to_string());
+
+        let mut saved_rooms = SavedRooms::load(temp_dir.path())?;
+        assert!(saved_rooms.rooms().is_empty());
+
+        saved_rooms.record_visit(&standup)?;
+        saved_rooms.record_visit(&random)?;
+        saved_rooms.record_visit(&standup)?;
+
+        // Visiting again doesn't add a duplicate, the most recent visit comes first
+        let loaded = SavedRooms::load(temp_dir.path())?;
+        assert_eq!(loaded.rooms().len(), 2);
+        assert_eq!(loaded.rooms()[0].identifier, standup.identifier);
+        assert_eq!(loaded.rooms()[0].name, "standup");
+        assert_eq!(loaded.rooms()[1].identifier, random.identifier);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(temp_dir.path().join("saved_rooms.json"))?
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_and_forget() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let room = Room::new("standup".to_string());
+
+        let mut saved_rooms = SavedRooms::load(temp_dir.path())?;
+        saved_rooms.record_visit(&room)?;
+
+        saved_rooms.rename(&room.identifier, Some("Team sync".to_string()))?;
+        let loaded = SavedRooms::load(temp_dir.path())?;
+        let saved = loaded.get(&room.identifier).unwrap();
+        assert_eq!(saved.nickname.as_deref(), Some("Team sync"));
+
+        // An empty nickname clears it
+        saved_rooms.rename(&room.identifier, Some(String::new()))?;
+        let loaded = SavedRooms::load(temp_dir.path())?;
+        assert!(loaded.get(&room.identifier).unwrap().nickname.is_none());
+
+        saved_rooms.forget(&room.identifier)?;
+        assert!(SavedRooms::load(temp_dir.path())?.rooms().is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use anyhow::{Context, Result};
+use p2panda_core::Hash;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::language::{detect_language, normalize_language};
+
+/// Entries kept when loading the cache, the most recently added ones
+const MAX_ENTRIES: usize = 10_000;
+
+/// What a cached translation was made from. The content and context are only stored as
+/// hashes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CacheKey {
+    content_hash: String,
+    /// Hash of the preceding messages the translation was made in the context of, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    context_hash: Option<String>,
+    source_language: Option<String>,
+    target_language: String,
+    /// Backend and model which translated, see [`TranslationBackendConfig::model_id`]
+    ///
+    /// [`TranslationBackendConfig::model_id`]: crate::config::TranslationBackendConfig::model_id
+    model_id: String,
+}
+
+impl CacheKey {
+    pub fn new(content: &str, context: &[String], target_language: &str, model_id: &str) -> Self {
+        let content = content.trim();
+        let context_hash =
+            (!context.is_empty()).then(|| Hash::new(context.join("\n").as_bytes()).to_hex());
+        Self {
+            content_hash: Hash::new(content.as_bytes()).to_hex(),
+            context_hash,
+            source_language: detect_language(content),
+            target_language: normalize_language(target_language),
+            model_id: model_id.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheRecord {
+    #[serde(flatten)]
+    key: CacheKey,
+    translation: String,
+}
+
+/// Translations made before, stored as JSON lines in the data directory so the same phrase
+/// is only translated once. The file is compacted on load, dropping replaced and the oldest
+/// entries.
+#[derive(Debug)]
+pub struct TranslationCache {
+    path: PathBuf,
+    entries: HashMap<CacheKey, String>,
+    hits: u64,
+    misses: u64,
+}
+
+impl TranslationCache {
+    /// Load the cache from the given data directory. A missing file is an empty cache.
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        Self::load_with_limit(data_dir, MAX_ENTRIES)
+    }
+
+    fn load_with_limit(data_dir: &Path, max_entries: usize) -> Result<Self> {
+        let path = data_dir.join("translation_cache.jsonl");
+        let mut records = Vec::new();
+
+        if path.exists() {
+            let file = File::open(&path)
+                .with_context(|| format!("Failed to open translation cache: {}", path.display()))?;
+
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                match serde_json::from_str::<CacheRecord>(&line) {
+                    Ok(record) => records.push(record),
+                    Err(e) => {
+                        tracing::warn!("Skipping malformed translation cache record: {}", e);
+                    }
+                }
+            }
+        }
+
+        // Keep the latest translation of each key, and only the newest entries
+        let total = records.len();
+        let mut seen = HashSet::new();
+        records.reverse();
+        records.retain(|record| seen.insert(record.key.clone()));
+        records.truncate(max_entries);
+        records.reverse();
+
+        let cache = Self {
+            path,
+            entries: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        };
+        if records.len() < total
+            && let Err(e) = cache.rewrite(&records)
+        {
+            tracing::warn!("Failed to compact translation cache: {}", e);
+        }
+
+        Ok(Self {
+            entries: records
+                .into_iter()
+                .map(|record| (record.key, record.translation))
+                .collect(),
+            ..cache
+        })
+    }
+
+    /// Replace the file with the given records
+    fn rewrite(&self, records: &[CacheRecord]) -> Result<()> {
+        let mut content = String::new();
+        for record in records {
+            content.push_str(
+                &serde_json::to_string(record).context("Failed to serialize translation cache")?,
+            );
+            content.push('\n');
+        }
+
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        fs::write(&temp_path, content).with_context(|| {
+            format!("Failed to write translation cache: {}", temp_path.display())
+        })?;
+        fs::rename(&temp_path, &self.path).with_context(|| {
+            format!(
+                "Failed to replace translation cache: {}",
+                self.path.display()
+            )
+        })
+    }
+
+    /// Look up a translation, counting the lookup as a hit or miss.
+    pub fn get(&mut self, key: &CacheKey) -> Option<&str> {
+        match self.entries.get(key) {
+            Some(translation) => {
+                self.hits += 1;
+                Some(translation)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: CacheKey, translation: String) -> Result<()> {
+        if self.entries.get(&key) == Some(&translation) {
+            return Ok(());
+        }
+
+        let record = CacheRecord { key, translation };
+        let mut line =
+            serde_json::to_string(&record).context("Failed to serialize translation cache")?;
+        line.push('\n');
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create data directory: {}", parent.display())
+            })?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| {
+                format!("Failed to open translation cache: {}", self.path.display())
+            })?;
+        file.write_all(line.as_bytes()).with_context(|| {
+            format!("Failed to write translation cache: {}", self.path.display())
+        })?;
+
+        self.entries.insert(record.key, record.translation);
+        Ok(())
+    }
+
+    /// Number of lookups which found a translation and which didn't
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cache_round_trip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let key = CacheKey::new("How are you doing today?", &[], "Spanish", "llama:default");
+
+        let mut cache = TranslationCache::load(temp_dir.path())?;
+        assert!(cache.get(&key).is_none());
+        cache.insert(key.clone(), "¿Cómo estás hoy?".to_string())?;
+        assert_eq!(cache.get(&key), Some("¿Cómo estás hoy?"));
+        assert_eq!(cache.stats(), (1, 1));
+
+        // Survives a restart, surrounding whitespace doesn't matter
+        let mut reloaded = TranslationCache::load(temp_dir.path())?;
+        let same_key = CacheKey::new(
+            "  How are you doing today?\n",
+            &[],
+            "Spanish",
+            "llama:default",
+        );
+        assert_eq!(reloaded.get(&same_key), Some("¿Cómo estás hoy?"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_key_includes_language_and_model() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut cache = TranslationCache::load(temp_dir.path())?;
+        cache.insert(
+            CacheKey::new("Good morning", &[], "Spanish", "llama:default"),
+            "Buenos días".to_string(),
+        )?;
+
+        assert!(
+            cache
+                .get(&CacheKey::new(
+                    "Good morning",
+                    &[],
+                    "French",
+                    "llama:default"
+                ))
+                .is_none()
+        );
+        assert!(
+            cache
+                .get(&CacheKey::new(
+                    "Good morning",
+                    &[],
+                    "Spanish",
+                    "openai:other"
+                ))
+                .is_none()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_key_includes_context() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut cache = TranslationCache::load(temp_dir.path())?;
+        let context = vec!["Are you coming to the river tomorrow?".to_string()];
+        cache.insert(
+            CacheKey::new("See you at the bank", &context, "Spanish", "llama:default"),
+            "Nos vemos en la orilla".to_string(),
+        )?;
+
+        assert_eq!(
+            cache.get(&CacheKey::new(
+                "See you at the bank",
+                &context,
+                "Spanish",
+                "llama:default"
+            )),
+            Some("Nos vemos en la orilla")
+        );
+        let other_context = vec!["I need to withdraw some money.".to_string()];
+        assert!(
+            cache
+                .get(&CacheKey::new(
+                    "See you at the bank",
+                    &other_context,
+                    "Spanish",
+                    "llama:default"
+                ))
+                .is_none()
+        );
+        assert!(
+            cache
+                .get(&CacheKey::new(
+                    "See you at the bank",
+                    &[],
+                    "Spanish",
+                    "llama:default"
+                ))
+                .is_none()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_key_normalizes_language() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut cache = TranslationCache::load(temp_dir.path())?;
+        cache.insert(
+            CacheKey::new("Good morning", &[], "Spanish", "llama:default"),
+            "Buenos días".to_string(),
+        )?;
+
+        for language in ["spanish", "spa", "Español"] {
+            assert_eq!(
+                cache.get(&CacheKey::new(
+                    "Good morning",
+                    &[],
+                    language,
+                    "llama:default"
+                )),
+                Some("Buenos días")
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_compacts_on_load() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let key = |content: &str| CacheKey::new(content, &[], "Spanish", "llama:default");
+
+        let mut cache = TranslationCache::load(temp_dir.path())?;
+        cache.insert(key("Good night"), "Buenas noches".to_string())?;
+        cache.insert(key("Good morning"), "Buen día".to_string())?;
+        cache.insert(key("Thank you"), "Gracias".to_string())?;
+        cache.insert(key("Good morning"), "Buenos días".to_string())?;
+
+        // The replaced translation and the oldest entry are dropped
+        let mut reloaded = TranslationCache::load_with_limit(temp_dir.path(), 2)?;
+        assert!(reloaded.get(&key("Good night")).is_none());
+        assert_eq!(reloaded.get(&key("Good morning")), Some("Buenos días"));
+        assert_eq!(reloaded.get(&key("Thank you")), Some("Gracias"));
+
+        let lines = fs::read_to_string(temp_dir.path().join("translation_cache.jsonl"))?;
+        assert_eq!(lines.lines().count(), 2);
+
+        Ok(())
+    }
+}
@@ -1,58 +1,213 @@
 use anyhow::Result;
-use tokio::sync::{OnceCell, mpsc};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::{Notify, OnceCell, mpsc, watch};
 use tracing::{debug, error, warn};
 
+use crate::config::{Config, TranslationBackendConfig};
 use crate::entities::chat::Message;
-use crate::llm::get_llm;
-use crate::translation::Translator;
+use crate::language::is_same_language;
+use crate::llm::{ModelStatus, load_ai_models};
+use crate::translation::{Backend, TranslationBackend};
+use crate::translation_cache::{CacheKey, TranslationCache};
 
 static IS_TRANSLATION_WORKER_DISABLED: OnceCell<bool> = OnceCell::const_new();
 
+/// How urgently a translation is needed. Higher priorities are translated first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// A message which isn't on screen
+    Background,
+    /// A message which is on screen
+    Visible,
+    /// A message of ours which is held back until it is translated
+    Outgoing,
+}
+
 #[derive(Debug, Clone)]
 pub struct TranslationRequest {
     pub message_id: u64,
     pub content: String,
     pub target_language: String,
+    pub priority: Priority,
+    /// Messages before this one in its room, oldest first, to translate it in context
+    pub context: Vec<String>,
+}
+
+/// Requests waiting for the translation worker. The most urgent request is translated next,
+/// among equally urgent ones the newest message.
+#[derive(Debug, Default)]
+struct TranslationQueue {
+    requests: Mutex<Vec<TranslationRequest>>,
+    /// Wakes the worker when a request is queued or the queue is closed
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl TranslationQueue {
+    fn push(&self, request: TranslationRequest) {
+        self.requests().push(request);
+        self.notify.notify_one();
+    }
+
+    /// Take the next request to translate, or `None` if nothing is queued.
+    fn pop(&self) -> Option<TranslationRequest> {
+        let mut requests = self.requests();
+        let next = requests
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, request)| (request.priority, request.message_id))
+            .map(|(index, _)| index)?;
+        Some(requests.swap_remove(next))
+    }
+
+    /// Wait for the next request to translate. Returns `None` once the queue is closed.
+    async fn next(&self) -> Option<TranslationRequest> {
+        loop {
+            if self.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let Some(request) = self.pop() {
+                return Some(request);
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Drop queued requests matching the message and the language, where `None` matches any.
+    /// Returns the number of dropped requests.
+    fn cancel(&self, message_id: Option<u64>, language: Option<&str>) -> usize {
+        let mut requests = self.requests();
+        let queued = requests.len();
+        requests.retain(|request| {
+            !(message_id.is_none_or(|message_id| request.message_id == message_id)
+                && language
+                    .is_none_or(|language| is_same_language(&request.target_language, language)))
+        });
+        queued - requests.len()
+    }
+
+    /// Move the messages on screen ahead of those which aren't.
+    fn prioritize(&self, visible: &HashSet<u64>) {
+        for request in self.requests().iter_mut() {
+            request.priority = match request.priority {
+                Priority::Outgoing => Priority::Outgoing,
+                _ if visible.contains(&request.message_id) => Priority::Visible,
+                _ => Priority::Background,
+            };
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    fn requests(&self) -> MutexGuard<'_, Vec<TranslationRequest>> {
+        self.requests
+            .lock()
+            .expect("translation queue lock poisoned")
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TranslationResponse {
     pub message_id: u64,
-    pub translation: String,
     pub language: String,
+    pub outcome: TranslationOutcome,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranslationOutcome {
+    /// Translation so far while it is still being generated, followed by a final outcome
+    Partial(String),
+    Translated(String),
+    /// The message couldn't be translated, with the reason
+    Failed(String),
 }
 
 pub struct TranslationService {
-    pub request_tx: mpsc::UnboundedSender<TranslationRequest>,
+    queue: Arc<TranslationQueue>,
     pub response_rx: mpsc::UnboundedReceiver<TranslationResponse>,
+    /// Progress of loading the local model, `None` if no model runs in this process
+    model_status_rx: Option<watch::Receiver<ModelStatus>>,
+    disable_ai: bool,
 }
 
 impl TranslationService {
-    pub fn new() -> Self {
-        let (request_tx, request_rx) = mpsc::unbounded_channel::<TranslationRequest>();
+    pub fn new(config: &Config) -> Self {
+        let queue = Arc::new(TranslationQueue::default());
         let (response_tx, response_rx) = mpsc::unbounded_channel::<TranslationResponse>();
 
+        // Load the local model in the background, so chatting can start right away
+        let model_status_rx = match &config.translation_backend {
+            TranslationBackendConfig::Llama(llama_config) if !config.disable_ai => {
+                Some(load_ai_models(llama_config.clone()))
+            }
+            _ => None,
+        };
+
         // Spawn background translation worker
-        tokio::spawn(translation_worker(request_rx, response_tx));
+        tokio::spawn(translation_worker(
+            config.translation_backend.clone(),
+            config.data_dir().ok(),
+            model_status_rx.clone(),
+            queue.clone(),
+            response_tx,
+        ));
 
         Self {
-            request_tx,
+            queue,
             response_rx,
+            model_status_rx,
+            disable_ai: config.disable_ai,
         }
     }
 
-    pub fn request_translation(&self, message: &Message, target_language: String) -> Result<()> {
-        let request = TranslationRequest {
+    /// Current state of loading the local model. Backends which need no loading are always
+    /// ready, `None` means nothing translates.
+    pub fn model_status(&self) -> Option<ModelStatus> {
+        match &self.model_status_rx {
+            Some(status_rx) => Some(status_rx.borrow().clone()),
+            None if !self.disable_ai => Some(ModelStatus::Ready),
+            None => None,
+        }
+    }
+
+    pub fn request_translation(
+        &self,
+        message: &Message,
+        target_language: String,
+        priority: Priority,
+        context: Vec<String>,
+    ) {
+        self.queue(TranslationRequest {
             message_id: message.id,
             content: message.content.clone(),
             target_language,
-        };
+            priority,
+            context,
+        });
+    }
+
+    pub fn queue(&self, request: TranslationRequest) {
+        self.queue.push(request);
+    }
 
-        self.request_tx
-            .send(request)
-            .map_err(|e| anyhow::anyhow!("Failed to send translation request: {}", e))?;
+    /// Drop queued translations of a message, into a language, or both. `None` matches any
+    /// message or language. Translations already being generated still finish.
+    pub fn cancel(&self, message_id: Option<u64>, target_language: Option<&str>) {
+        let cancelled = self.queue.cancel(message_id, target_language);
+        if cancelled > 0 {
+            debug!("Cancelled {} queued translations", cancelled);
+        }
+    }
 
-        Ok(())
+    /// Translate the given messages, which are on screen, before the other queued ones.
+    pub fn prioritize(&self, visible: &HashSet<u64>) {
+        self.queue.prioritize(visible);
     }
 
     pub fn try_recv_translation(&mut self) -> Option<TranslationResponse> {
@@ -60,13 +215,23 @@ impl TranslationService {
     }
 }
 
+impl Drop for TranslationService {
+    fn drop(&mut self) {
+        // Stop the worker
+        self.queue.close();
+    }
+}
+
 pub fn disable_translation_worker() -> Result<()> {
     IS_TRANSLATION_WORKER_DISABLED.set(true)?;
     Ok(())
 }
 
 async fn translation_worker(
-    mut request_rx: mpsc::UnboundedReceiver<TranslationRequest>,
+    backend_config: TranslationBackendConfig,
+    data_dir: Option<PathBuf>,
+    model_status_rx: Option<watch::Receiver<ModelStatus>>,
+    queue: Arc<TranslationQueue>,
     response_tx: mpsc::UnboundedSender<TranslationResponse>,
 ) {
     if let Some(is_translation_worker_disabled) = IS_TRANSLATION_WORKER_DISABLED.get() {
@@ -78,44 +243,228 @@ async fn translation_worker(
 
     debug!("Translation worker started");
 
+    let respond = |request: &TranslationRequest, outcome: TranslationOutcome| {
+        let response = TranslationResponse {
+            message_id: request.message_id,
+            language: request.target_language.clone(),
+            outcome,
+        };
+        if let Err(e) = response_tx.send(response) {
+            error!("Failed to send translation response: {}", e);
+        }
+    };
+
+    // Nothing can be translated, let every request know why
+    let fail_all = async |error: String| {
+        while let Some(request) = queue.next().await {
+            respond(&request, TranslationOutcome::Failed(error.clone()));
+        }
+    };
+
+    // Wait for the local model to be verified and loaded in the background. Loading it here
+    // first would skip the checksum and the progress reports.
+    if let Some(mut status_rx) = model_status_rx {
+        let status = status_rx
+            .wait_for(|status| matches!(status, ModelStatus::Ready | ModelStatus::Failed(_)))
+            .await
+            .map(|status| status.clone());
+        match status {
+            Ok(ModelStatus::Ready) => debug!("Translation model ready"),
+            Ok(ModelStatus::Failed(error)) => {
+                fail_all(format!("Translation model failed: {}", error)).await;
+                return;
+            }
+            _ => {
+                fail_all("Translation model stopped loading".to_string()).await;
+                return;
+            }
+        }
+    }
+
     // Initialize translator once for the worker
-    let translator = match get_llm().await {
-        Ok(llm) => Translator::new(llm.clone()),
+    let translator = match Backend::from_config(&backend_config).await {
+        Ok(backend) => backend,
         Err(e) => {
             error!("Failed to initialize translator: {}", e);
+            fail_all(format!("Translator unavailable: {}", e)).await;
             return;
         }
     };
 
-    while let Some(request) = request_rx.recv().await {
+    // Reuse translations made before. The cache is best-effort: translating works without it.
+    let model_id = backend_config.model_id();
+    let mut cache = match (&model_id, &data_dir) {
+        (Some(_), Some(data_dir)) => match TranslationCache::load(data_dir) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn!("Failed to load translation cache: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    while let Some(request) = queue.next().await {
         debug!(
             "Processing translation request for message {}",
             request.message_id
         );
 
+        let cache_key = model_id.as_ref().map(|model_id| {
+            CacheKey::new(
+                &request.content,
+                &request.context,
+                &request.target_language,
+                model_id,
+            )
+        });
+        if let (Some(cache), Some(cache_key)) = (&mut cache, &cache_key) {
+            let cached = cache.get(cache_key).map(str::to_string);
+            let (hits, misses) = cache.stats();
+            debug!(
+                "Translation cache {} for message {} ({} hits, {} misses)",
+                if cached.is_some() { "hit" } else { "miss" },
+                request.message_id,
+                hits,
+                misses
+            );
+
+            if let Some(translation) = cached {
+                respond(&request, TranslationOutcome::Translated(translation));
+                continue;
+            }
+        }
+
+        let on_partial = |partial: &str| {
+            respond(&request, TranslationOutcome::Partial(partial.to_string()));
+        };
+
         match translator
-            .translate(&request.content, &request.target_language)
+            .translate_streaming(
+                &request.content,
+                &request.target_language,
+                &request.context,
+                on_partial,
+            )
             .await
         {
             Ok(translation) => {
-                let response = TranslationResponse {
-                    message_id: request.message_id,
-                    translation,
-                    language: request.target_language,
-                };
-
-                if let Err(e) = response_tx.send(response) {
-                    error!("Failed to send translation response: {}", e);
+                if let (Some(cache), Some(cache_key)) = (&mut cache, cache_key)
+                    && let Err(e) = cache.insert(cache_key, translation.clone())
+                {
+                    warn!("Failed to save translation to cache: {}", e);
                 }
+
+                respond(&request, TranslationOutcome::Translated(translation));
             }
             Err(e) => {
                 warn!(
                     "Translation failed for message {}: {}",
                     request.message_id, e
                 );
+                respond(&request, TranslationOutcome::Failed(e.to_string()));
             }
         }
     }
 
     debug!("Translation worker stopped");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(message_id: u64, target_language: &str, priority: Priority) -> TranslationRequest {
+        TranslationRequest {
+            message_id,
+            content: format!("Message {}", message_id),
+            target_language: target_language.to_string(),
+            priority,
+            context: Vec::new(),
+        }
+    }
+
+    fn drain(queue: &TranslationQueue) -> Vec<(u64, String)> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|request| (request.message_id, request.target_language))
+            .collect()
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let queue = TranslationQueue::default();
+        queue.push(request(1, "Spanish", Priority::Background));
+        queue.push(request(2, "Spanish", Priority::Visible));
+        queue.push(request(3, "Spanish", Priority::Background));
+        queue.push(request(4, "Spanish", Priority::Visible));
+        queue.push(request(0, "French", Priority::Outgoing));
+
+        // Outgoing first, then on screen, each newest first
+        let order: Vec<u64> = drain(&queue).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(order, vec![0, 4, 2, 3, 1]);
+    }
+
+    #[test]
+    fn test_queue_prioritize() {
+        let queue = TranslationQueue::default();
+        queue.push(request(1, "Spanish", Priority::Background));
+        queue.push(request(2, "Spanish", Priority::Visible));
+        queue.push(request(3, "Spanish", Priority::Background));
+
+        // Scrolled up to the oldest message
+        queue.prioritize(&HashSet::from([1]));
+        let order: Vec<u64> = drain(&queue).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(order, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn test_queue_cancel() {
+        let queue = TranslationQueue::default();
+        queue.push(request(1, "Spanish", Priority::Background));
+        queue.push(request(1, "French", Priority::Outgoing));
+        queue.push(request(2, "Spanish", Priority::Background));
+        queue.push(request(3, "German", Priority::Background));
+
+        assert_eq!(queue.cancel(Some(1), Some("Spanish")), 1);
+        assert_eq!(queue.cancel(None, Some("German")), 1);
+        assert_eq!(queue.cancel(Some(2), None), 1);
+        assert_eq!(drain(&queue), vec![(1, "French".to_string())]);
+
+        // Other spellings of the same language are cancelled too
+        queue.push(request(4, "spanish", Priority::Background));
+        queue.push(request(5, "spa", Priority::Background));
+        assert_eq!(queue.cancel(None, Some("Spanish")), 2);
+    }
+
+    #[tokio::test]
+    async fn test_queue_close() {
+        let queue = Arc::new(TranslationQueue::default());
+        let worker = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.next().await.map(|request| request.message_id) }
+        });
+
+        queue.push(request(1, "Spanish", Priority::Background));
+        assert_eq!(worker.await.unwrap(), Some(1));
+
+        queue.close();
+        assert!(queue.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_model_status_without_local_model() {
+        let config = Config {
+            translation_backend: TranslationBackendConfig::Dictionary { path: None },
+            ..Config::default()
+        };
+        let service = TranslationService::new(&config);
+        assert_eq!(service.model_status(), Some(ModelStatus::Ready));
+
+        let config = Config {
+            disable_ai: true,
+            ..config
+        };
+        let service = TranslationService::new(&config);
+        assert_eq!(service.model_status(), None);
+    }
+}
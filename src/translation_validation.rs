@@ -0,0 +1,230 @@
+use std::fmt;
+
+use crate::language::{detect_language, is_same_language, is_written_in};
+
+/// Labels models put in front of their translation, e.g. "Output: Bonjour"
+const OUTPUT_LABELS: &[&str] = &[
+    "output",
+    "translation",
+    "translated text",
+    "here is the translation",
+    "here's the translation",
+];
+
+/// Pairs of quotes models wrap their translation in
+const QUOTES: &[(char, char)] = &[
+    ('"', '"'),
+    ('\'', '\''),
+    ('“', '”'),
+    ('„', '“'),
+    ('‘', '’'),
+    ('«', '»'),
+    ('「', '」'),
+];
+
+/// A translation may be this many times longer or shorter than the message
+const MAX_LENGTH_RATIO: usize = 4;
+
+/// Characters a translation of a short message may be longer than allowed by the ratio
+const LENGTH_SLACK: usize = 20;
+
+/// Messages shorter than this aren't checked for translations which are too short
+const MIN_CHECKED_LENGTH: usize = 20;
+
+/// Why a model output doesn't look like a translation of the message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvalidTranslation {
+    Empty,
+    TooLong,
+    TooShort,
+    /// Written in a script the target language doesn't use
+    WrongScript(String),
+    /// Still in the language of the message, e.g. an answer instead of a translation
+    NotTranslated(String),
+}
+
+impl fmt::Display for InvalidTranslation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Translation is empty"),
+            Self::TooLong => write!(f, "Translation is much longer than the message"),
+            Self::TooShort => write!(f, "Translation is much shorter than the message"),
+            Self::WrongScript(language) => {
+                write!(f, "Translation is not written in a {} script", language)
+            }
+            Self::NotTranslated(language) => write!(f, "Translation is still in {}", language),
+        }
+    }
+}
+
+impl std::error::Error for InvalidTranslation {}
+
+/// Strip what models add around a translation of `input`: labels like "Output:", quotes and
+/// commentary on further lines or in a trailing note.
+pub fn clean_output(output: &str, input: &str) -> String {
+    let input = input.trim();
+    let mut output = output.trim();
+
+    // Labels may be on a line of their own, so they go before looking at lines
+    if let Some((label, rest)) = output.split_once(':') {
+        let label = label.trim().to_lowercase();
+        if OUTPUT_LABELS.contains(&label.as_str()) && !input.to_lowercase().starts_with(&label) {
+            output = rest.trim();
+        }
+    }
+
+    // Commentary follows the translation of a single line on its own lines, and an
+    // introduction like "Here is the French translation:" precedes it
+    if !input.contains('\n') {
+        let mut lines = output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let first = lines.next().unwrap_or_default();
+        output = match lines.next() {
+            Some(second) if first.ends_with(':') && !input.ends_with(':') => second,
+            _ => first,
+        };
+    }
+
+    if let Some(note) = output.find("(Note").or_else(|| output.find("(note"))
+        && !input.contains('(')
+    {
+        output = output[..note].trim_end();
+    }
+
+    for (open, close) in QUOTES {
+        if output.chars().count() >= 2
+            && output.starts_with(*open)
+            && output.ends_with(*close)
+            && !(input.starts_with(*open) && input.ends_with(*close))
+        {
+            output = output[open.len_utf8()..output.len() - close.len_utf8()].trim();
+            break;
+        }
+    }
+
+    output.to_string()
+}
+
+/// Check that a cleaned model output looks like a translation of `input` into
+/// `target_language`.
+pub fn validate_translation(
+    output: &str,
+    input: &str,
+    target_language: &str,
+) -> Result<(), InvalidTranslation> {
+    let input = input.trim();
+    let output_length = output.chars().count();
+    let input_length = input.chars().count();
+
+    if output.is_empty() {
+        return Err(InvalidTranslation::Empty);
+    }
+    if output_length > input_length * MAX_LENGTH_RATIO + LENGTH_SLACK {
+        return Err(InvalidTranslation::TooLong);
+    }
+    if input_length >= MIN_CHECKED_LENGTH && output_length * MAX_LENGTH_RATIO < input_length {
+        return Err(InvalidTranslation::TooShort);
+    }
+
+    // Names and the like are kept as they are, whatever the script
+    if output != input && is_written_in(output, target_language) == Some(false) {
+        return Err(InvalidTranslation::WrongScript(target_language.to_string()));
+    }
+
+    if let Some(source_language) = detect_language(input)
+        && !is_same_language(&source_language, target_language)
+        && detect_language(output)
+            .is_some_and(|language| is_same_language(&language, &source_language))
+    {
+        return Err(InvalidTranslation::NotTranslated(source_language));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_output() {
+        assert_eq!(clean_output("Output: Bonjour", "Hello"), "Bonjour");
+        assert_eq!(clean_output("  \"Bonjour\"\n", "Hello"), "Bonjour");
+        assert_eq!(clean_output("Translation: «Bonjour»", "Hello"), "Bonjour");
+        assert_eq!(
+            clean_output(
+                "Bonjour\n\nThis is the French translation of \"Hello\".",
+                "Hello"
+            ),
+            "Bonjour"
+        );
+        assert_eq!(
+            clean_output("Salut (Note: this is informal)", "Hi"),
+            "Salut"
+        );
+        assert_eq!(
+            clean_output("Here is the translation:\n\nBonjour", "Hello"),
+            "Bonjour"
+        );
+        assert_eq!(
+            clean_output(
+                "Here is the French translation:\nBonjour\n\nLet me know!",
+                "Hello"
+            ),
+            "Bonjour"
+        );
+
+        // Kept when the message itself has them
+        assert_eq!(
+            clean_output("\"Bonjour\", dit-il", "\"Hello\", he said"),
+            "\"Bonjour\", dit-il"
+        );
+        assert_eq!(
+            clean_output("Translation: ready", "Translation: done"),
+            "Translation: ready"
+        );
+    }
+
+    #[test]
+    fn test_validate_translation() {
+        let input = "Where is the library? I need to return these books today.";
+        assert_eq!(
+            validate_translation(
+                "¿Dónde está la biblioteca? Necesito devolver estos libros hoy.",
+                input,
+                "Spanish"
+            ),
+            Ok(())
+        );
+
+        assert_eq!(
+            validate_translation("", input, "Spanish"),
+            Err(InvalidTranslation::Empty)
+        );
+        assert_eq!(
+            validate_translation("Sí", input, "Spanish"),
+            Err(InvalidTranslation::TooShort)
+        );
+        assert_eq!(
+            validate_translation(&"Lo siento, no sé. ".repeat(20), input, "Spanish"),
+            Err(InvalidTranslation::TooLong)
+        );
+        assert_eq!(
+            validate_translation("Где библиотека? Мне нужно вернуть книги.", input, "Spanish"),
+            Err(InvalidTranslation::WrongScript("Spanish".to_string()))
+        );
+        assert_eq!(
+            validate_translation(
+                "The library is on the second floor, next to the reading room.",
+                input,
+                "Spanish"
+            ),
+            Err(InvalidTranslation::NotTranslated("English".to_string()))
+        );
+
+        // Names stay as they are
+        assert_eq!(validate_translation("Alice", "Alice", "Japanese"), Ok(()));
+    }
+}
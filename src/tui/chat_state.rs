@@ -1,73 +1,282 @@
 use anyhow::Result;
 use crossterm::event::{KeyCode, KeyModifiers};
+use p2panda_core::PublicKey;
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout, Rect, Size},
+    layout::{Constraint, Direction, Flex, Layout, Rect, Size},
     style::{Color, Style},
-    widgets::{Block, Borders, Paragraph, StatefulWidget, Widget, Wrap},
+    text::{Line, Span},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, StatefulWidget, Widget, Wrap,
+    },
 };
 use std::collections::HashSet;
-use tui_scrollview::{ScrollView, ScrollViewState};
+use std::time::SystemTime;
+use tui_scrollview::ScrollView;
 
 use crate::config::Config;
-use crate::entities::chat::Chat;
+use crate::entities::chat::TranslationState;
+use crate::identity::{Identity, fingerprint};
+use crate::language::{is_same_language, supported_languages};
+use crate::llm::ModelStatus;
 use crate::p2p::{ChatGroup, ChatNetworkService, NetworkError, NetworkEvent, NetworkMessage};
-use crate::room_manager::Room;
-use crate::translation_service::{TranslationRequest, TranslationService};
-use crate::tui::{AppState, State};
+use crate::room_manager::{Room, copy_to_clipboard};
+use crate::translation_service::{
+    Priority, TranslationOutcome, TranslationRequest, TranslationService,
+};
+use crate::tui::main_menu_state::MainMenuState;
+use crate::tui::room_state::{ConnectionStatus, Presence, RoomState};
+use crate::tui::{AppState, State, format_time_ago, render_model_status_bar};
+
+/// Width of the room sidebar
+const SIDEBAR_WIDTH: u16 = 24;
 
 #[derive(Debug, Clone)]
-pub enum ConnectionStatus {
-    Connecting,
-    Connected,
-    Disconnected,
-    Error(String),
+pub enum ChatInputMode {
+    Message,
+    CreatingRoom,
+    JoiningRoom,
+    PickingLanguage,
 }
 
 #[derive(Debug)]
 pub struct ChatState {
-    pub chat: Chat,
+    /// Joined rooms in sidebar order. Never empty.
+    pub rooms: Vec<RoomState>,
+    /// Index of the room which is shown
+    pub active_room: usize,
     pub input: String,
+    pub input_mode: ChatInputMode,
+    pub room_input: String,
+    pub status_message: String,
     pub translation_requests_sent: HashSet<u64>,
-    pub room: Room,
-    pub chat_group: ChatGroup,
+    /// Requested translations of messages which aren't needed anymore, into the given language
+    /// or any, to be cancelled on the next update
+    pub cancelled_translations: Vec<(u64, Option<String>)>,
+    /// Selection in the language picker
+    pub language_picker: ListState,
     pub network_service: ChatNetworkService,
-    pub pending_outgoing_messages: Vec<String>,
-    pub subscribed: bool,
-    pub connection_status: ConnectionStatus,
     pub show_translations: bool,
-    pub messages_scroll_state: ScrollViewState,
-    pub translations_scroll_state: ScrollViewState,
+    pub public_key: Option<PublicKey>,
+    pub model_status: Option<ModelStatus>,
 }
 
 impl ChatState {
-    pub fn with_room(room: Room) -> Self {
-        let chat_group = room.to_chat_group();
+    pub fn with_room(room: Room, config: &Config) -> Self {
+        // Load our identity before the network task starts, so both agree on the same key
+        let public_key = match config
+            .identity_path()
+            .and_then(|path| Identity::load_or_create(&path))
+        {
+            Ok(identity) => Some(identity.public_key()),
+            Err(e) => {
+                tracing::warn!("Failed to load identity: {}", e);
+                None
+            }
+        };
+
         let mut network_service = ChatNetworkService::new();
 
-        // Initialize the background network task
-        network_service.initialize_channels();
+        // Initialize the background network task, shared by all joined rooms
+        network_service.initialize_channels(config);
 
         Self {
-            chat: Chat::new(),
+            rooms: vec![RoomState::new(room, config)],
+            active_room: 0,
             input: String::new(),
+            input_mode: ChatInputMode::Message,
+            room_input: String::new(),
+            status_message: String::new(),
             translation_requests_sent: HashSet::new(),
-            room,
-            chat_group,
+            cancelled_translations: Vec::new(),
+            language_picker: ListState::default(),
             network_service,
-            pending_outgoing_messages: Vec::new(),
-            subscribed: false,
-            connection_status: ConnectionStatus::Connecting,
             show_translations: true, // Default to showing translations
-            messages_scroll_state: ScrollViewState::default(),
-            translations_scroll_state: ScrollViewState::default(),
+            public_key,
+            model_status: None,
+        }
+    }
+
+    /// Join a room and show it. Rooms which were joined before are only switched to.
+    pub fn join_room(&mut self, room: Room, config: &Config) {
+        let chat_group = room.to_chat_group();
+        match self.room_index(&chat_group) {
+            Some(index) => self.switch_to_room(index),
+            None => {
+                self.rooms.push(RoomState::new(room, config));
+                self.switch_to_room(self.rooms.len() - 1);
+            }
+        }
+    }
+
+    /// Leave the shown room. Returns false if it was the last one.
+    fn leave_active_room(&mut self) -> bool {
+        self.drop_translation_requests(self.active_room);
+        let room_state = self.rooms.remove(self.active_room);
+        if let Err(e) = self.network_service.unsubscribe(room_state.chat_group) {
+            tracing::warn!("Failed to unsubscribe from chat group: {}", e);
+        }
+        tracing::info!("Left room: {}", room_state.room.identifier);
+
+        if self.rooms.is_empty() {
+            return false;
+        }
+        self.switch_to_room(self.active_room.min(self.rooms.len() - 1));
+        true
+    }
+
+    fn switch_to_room(&mut self, index: usize) {
+        if index != self.active_room {
+            self.drop_translation_requests(self.active_room);
+        }
+        self.active_room = index;
+        let room_state = &mut self.rooms[index];
+        room_state.unread = 0;
+        room_state.scroll_to_bottom();
+    }
+
+    /// Cancel the queued translations of a room which is not shown anymore. Its messages are
+    /// requested again when it is shown.
+    fn drop_translation_requests(&mut self, index: usize) {
+        let Some(room_state) = self.rooms.get(index) else {
+            return;
+        };
+        for message in &room_state.chat.messages {
+            if self.translation_requests_sent.remove(&message.id) {
+                self.cancelled_translations.push((message.id, None));
+            }
         }
     }
 
-    fn scroll_to_bottom(&mut self) {
-        // Auto-scroll to the bottom by setting scroll position to max
-        self.messages_scroll_state.scroll_to_bottom();
-        self.translations_scroll_state.scroll_to_bottom();
+    fn room_index(&self, chat_group: &ChatGroup) -> Option<usize> {
+        self.rooms
+            .iter()
+            .position(|room_state| &room_state.chat_group == chat_group)
+    }
+
+    fn active_room_mut(&mut self) -> &mut RoomState {
+        &mut self.rooms[self.active_room]
+    }
+
+    /// Open the language picker with the language of the shown room selected.
+    fn open_language_picker(&mut self) {
+        let target_language = &self.rooms[self.active_room].chat.target_language;
+        let selected = supported_languages()
+            .iter()
+            .position(|language| is_same_language(language, target_language))
+            .unwrap_or(0);
+        self.language_picker.select(Some(selected));
+        self.input_mode = ChatInputMode::PickingLanguage;
+    }
+
+    fn handle_language_picker(&mut self, key: KeyCode) {
+        let languages = supported_languages();
+        match key {
+            KeyCode::Esc => {
+                self.input_mode = ChatInputMode::Message;
+            }
+            KeyCode::Up => self.language_picker.select_previous(),
+            KeyCode::Down => self.language_picker.select_next(),
+            KeyCode::PageUp => self.language_picker.scroll_up_by(10),
+            KeyCode::PageDown => self.language_picker.scroll_down_by(10),
+            KeyCode::Char(c) => {
+                // Jump to the first language starting with the typed letter
+                if let Some(index) = languages.iter().position(|language| {
+                    language
+                        .chars()
+                        .next()
+                        .is_some_and(|first| first.eq_ignore_ascii_case(&c))
+                }) {
+                    self.language_picker.select(Some(index));
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(language) = self
+                    .language_picker
+                    .selected()
+                    .and_then(|index| languages.get(index))
+                {
+                    self.switch_target_language(language.to_string());
+                }
+                self.input_mode = ChatInputMode::Message;
+            }
+            _ => {}
+        }
+    }
+
+    /// Read the shown room in another language. Queued translations into the previous language
+    /// are cancelled and the messages translated again, those on screen first.
+    fn switch_target_language(&mut self, language: String) {
+        let room_state = &mut self.rooms[self.active_room];
+        let previous_language = room_state.chat.target_language.clone();
+        if is_same_language(&previous_language, &language) {
+            return;
+        }
+
+        for message in &room_state.chat.messages {
+            if self.translation_requests_sent.remove(&message.id) {
+                self.cancelled_translations
+                    .push((message.id, Some(previous_language.clone())));
+            }
+        }
+        room_state.set_target_language(language.clone());
+        self.status_message = format!(
+            "Translating {} into {}",
+            room_state.display_name(),
+            language
+        );
+    }
+
+    fn handle_room_input(&mut self, key: KeyCode, config: &Config) -> Result<Option<AppState>> {
+        match key {
+            KeyCode::Esc => {
+                self.input_mode = ChatInputMode::Message;
+                self.room_input.clear();
+            }
+            KeyCode::Char(c) => {
+                self.room_input.push(c);
+            }
+            KeyCode::Backspace => {
+                self.room_input.pop();
+            }
+            KeyCode::Enter if !self.room_input.is_empty() => {
+                let input = std::mem::take(&mut self.room_input);
+                match self.input_mode {
+                    ChatInputMode::CreatingRoom => {
+                        let room = Room::new(input);
+                        tracing::info!("Created room: {}", room.identifier);
+                        self.status_message = match copy_to_clipboard(&room.identifier) {
+                            Ok(()) => {
+                                format!("Room created! ID copied to clipboard: {}", room.identifier)
+                            }
+                            Err(e) => {
+                                tracing::warn!("Failed to copy to clipboard: {}", e);
+                                format!(
+                                    "Room created: {} (Clipboard copy failed: {})",
+                                    room.identifier, e
+                                )
+                            }
+                        };
+                        self.join_room(room, config);
+                    }
+                    ChatInputMode::JoiningRoom => match Room::from_identifier(input) {
+                        Ok(room) => {
+                            tracing::info!("Joining room: {}", room.identifier);
+                            self.status_message = format!("Joined room: {}", room.name);
+                            self.join_room(room, config);
+                        }
+                        Err(e) => {
+                            tracing::warn!("Failed to parse room ID: {}", e);
+                            self.status_message = format!("Invalid room ID: {}", e);
+                        }
+                    },
+                    ChatInputMode::Message | ChatInputMode::PickingLanguage => {}
+                }
+                self.input_mode = ChatInputMode::Message;
+            }
+            _ => {}
+        }
+        Ok(None)
     }
 }
 
@@ -80,11 +289,68 @@ impl State for ChatState {
     ) -> Result<Option<AppState>> {
         match (key, modifiers) {
             (KeyCode::Char('q'), KeyModifiers::CONTROL) => Ok(Some(AppState::Quit)),
+            _ if matches!(self.input_mode, ChatInputMode::PickingLanguage) => {
+                self.handle_language_picker(key);
+                Ok(None)
+            }
+            _ if !matches!(self.input_mode, ChatInputMode::Message) => {
+                self.handle_room_input(key, config)
+            }
             (KeyCode::Char('t'), KeyModifiers::CONTROL) => {
-                // Toggle translations panel (only if AI is not disabled)
-                if !config.disable_ai {
-                    self.show_translations = !self.show_translations;
+                // Toggle translations panel. Without AI it still shows translations attached by
+                // senders.
+                self.show_translations = !self.show_translations;
+                Ok(None)
+            }
+            (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                self.input_mode = ChatInputMode::CreatingRoom;
+                Ok(None)
+            }
+            (KeyCode::Char('o'), KeyModifiers::CONTROL) => {
+                self.input_mode = ChatInputMode::JoiningRoom;
+                Ok(None)
+            }
+            (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
+                self.open_language_picker();
+                Ok(None)
+            }
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                let retried = self.active_room_mut().retry_failed_translations();
+                for message_id in &retried {
+                    self.translation_requests_sent.remove(message_id);
+                }
+                self.status_message = match retried.len() {
+                    0 => "No failed translation to retry".to_string(),
+                    1 => "Retrying translation".to_string(),
+                    count => format!("Retrying {} translations", count),
+                };
+                Ok(None)
+            }
+            (KeyCode::Up, KeyModifiers::SHIFT) => {
+                self.active_room_mut().select_previous_message();
+                Ok(None)
+            }
+            (KeyCode::Down, KeyModifiers::SHIFT) => {
+                self.active_room_mut().select_next_message();
+                Ok(None)
+            }
+            (KeyCode::Esc, _) => {
+                self.active_room_mut().selected_message = None;
+                Ok(None)
+            }
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                if self.leave_active_room() {
+                    Ok(None)
+                } else {
+                    Ok(Some(AppState::MainMenu(MainMenuState::new(config))))
                 }
+            }
+            (KeyCode::Tab, _) => {
+                self.switch_to_room((self.active_room + 1) % self.rooms.len());
+                Ok(None)
+            }
+            (KeyCode::BackTab, _) => {
+                self.switch_to_room((self.active_room + self.rooms.len() - 1) % self.rooms.len());
                 Ok(None)
             }
             (KeyCode::Char(c), KeyModifiers::NONE) => {
@@ -101,46 +367,45 @@ impl State for ChatState {
             }
             (KeyCode::Enter, _) => {
                 if !self.input.is_empty() {
-                    let content = self.input.clone();
-                    let _message = self
-                        .chat
-                        .add_message(content.clone(), config.username.clone())?;
-
-                    // Auto-scroll to bottom when new message is added
-                    self.scroll_to_bottom();
-
-                    // Queue message for network broadcasting
-                    self.pending_outgoing_messages.push(content);
-
-                    self.input.clear();
+                    let content = std::mem::take(&mut self.input);
+                    let public_key = self.public_key;
+                    // Without a ready model, waiting for translations would only delay it
+                    let translate =
+                        !config.disable_ai && matches!(self.model_status, Some(ModelStatus::Ready));
+                    self.active_room_mut()
+                        .send_message(content, config, public_key, translate)?;
                 }
                 Ok(None)
             }
             (KeyCode::Up, KeyModifiers::NONE) => {
                 // Scroll up in messages
-                self.messages_scroll_state.scroll_up();
-                self.translations_scroll_state.scroll_up();
+                let room_state = self.active_room_mut();
+                room_state.messages_scroll_state.scroll_up();
+                room_state.translations_scroll_state.scroll_up();
                 Ok(None)
             }
             (KeyCode::Down, KeyModifiers::NONE) => {
                 // Scroll down in messages
-                self.messages_scroll_state.scroll_down();
-                self.translations_scroll_state.scroll_down();
+                let room_state = self.active_room_mut();
+                room_state.messages_scroll_state.scroll_down();
+                room_state.translations_scroll_state.scroll_down();
                 Ok(None)
             }
             (KeyCode::PageUp, _) => {
                 // Scroll up by page
+                let room_state = self.active_room_mut();
                 for _ in 0..10 {
-                    self.messages_scroll_state.scroll_up();
-                    self.translations_scroll_state.scroll_up();
+                    room_state.messages_scroll_state.scroll_up();
+                    room_state.translations_scroll_state.scroll_up();
                 }
                 Ok(None)
             }
             (KeyCode::PageDown, _) => {
                 // Scroll down by page
+                let room_state = self.active_room_mut();
                 for _ in 0..10 {
-                    self.messages_scroll_state.scroll_down();
-                    self.translations_scroll_state.scroll_down();
+                    room_state.messages_scroll_state.scroll_down();
+                    room_state.translations_scroll_state.scroll_down();
                 }
                 Ok(None)
             }
@@ -149,117 +414,278 @@ impl State for ChatState {
     }
 
     fn render(&mut self, f: &mut Frame, config: &Config) {
-        // Main vertical layout: messages area and input at bottom
+        let area = render_model_status_bar(f, self.model_status.as_ref());
+
+        // Main vertical layout: messages area, status if present and input at bottom
+        let constraints = if self.status_message.is_empty() {
+            vec![Constraint::Min(0), Constraint::Length(3)]
+        } else {
+            vec![
+                Constraint::Min(0),
+                Constraint::Length(3),
+                Constraint::Length(3),
+            ]
+        };
         let main_chunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(0), Constraint::Length(3)])
-            .split(f.area());
+            .constraints(constraints)
+            .split(area);
 
-        let messages_area = main_chunks[0];
-        let input_area = main_chunks[1];
+        let body_area = main_chunks[0];
+        let input_area = main_chunks[main_chunks.len() - 1];
 
-        // Render input at bottom (full width)
+        // Render status and input at bottom (full width)
+        if !self.status_message.is_empty() {
+            render_status(f, self, main_chunks[1]);
+        }
         render_input_box(f, self, input_area);
 
-        // Determine if we should show translations (AI enabled and user wants to see them)
-        if self.show_translations && !config.disable_ai {
+        // Split the body horizontally: room sidebar | messages area
+        let body_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(SIDEBAR_WIDTH), Constraint::Min(0)])
+            .split(body_area);
+
+        render_room_sidebar(f, self, body_chunks[0]);
+
+        let messages_area = body_chunks[1];
+        let public_key = self.public_key;
+        let show_translations = self.show_translations;
+        let room_state = self.active_room_mut();
+
+        // Determine if we should show translations
+        if show_translations {
             // Split messages area horizontally: messages | translations
             let message_chunks = Layout::default()
                 .direction(Direction::Horizontal)
                 .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .split(messages_area);
 
-            render_messages_pane(f, self, message_chunks[0]);
-            render_translation_pane(f, self, message_chunks[1], config);
+            render_messages_pane(f, room_state, message_chunks[0]);
+            render_translation_pane(f, room_state, message_chunks[1], config, public_key);
         } else {
             // Show only messages (full width)
-            render_messages_pane(f, self, messages_area);
+            render_messages_pane(f, room_state, messages_area);
+        }
+
+        if matches!(self.input_mode, ChatInputMode::PickingLanguage) {
+            render_language_picker(f, self, body_area);
         }
     }
 
     fn update(&mut self, translation_service: &mut TranslationService, config: &Config) {
+        self.model_status = translation_service.model_status();
+
+        for (message_id, language) in self.cancelled_translations.drain(..) {
+            translation_service.cancel(Some(message_id), language.as_deref());
+        }
+
         // Process any completed translations
         while let Some(response) = translation_service.try_recv_translation() {
-            self.chat
-                .update_translation(response.message_id, response.translation);
+            let translation = match response.outcome {
+                // Translations still being generated are only shown
+                TranslationOutcome::Partial(partial) => {
+                    if let Some(room_state) = self.rooms.iter_mut().find(|room_state| {
+                        is_same_language(&room_state.chat.target_language, &response.language)
+                            && room_state
+                                .chat
+                                .messages
+                                .iter()
+                                .any(|message| message.id == response.message_id)
+                    }) {
+                        room_state
+                            .chat
+                            .update_partial_translation(response.message_id, partial);
+                    }
+                    continue;
+                }
+                TranslationOutcome::Translated(translation) => Ok(translation),
+                TranslationOutcome::Failed(error) => Err(error),
+            };
+
+            // Translations of our own messages are attached to them before sending
+            if self.rooms.iter_mut().any(|room_state| {
+                room_state.attach_outgoing_translation(
+                    response.message_id,
+                    &response.language,
+                    translation.as_deref().ok(),
+                )
+            }) {
+                continue;
+            }
+
+            let Some(room_state) = self.rooms.iter_mut().find(|room_state| {
+                room_state
+                    .chat
+                    .messages
+                    .iter()
+                    .any(|message| message.id == response.message_id)
+            }) else {
+                // The room was left in the meantime
+                continue;
+            };
+            if !is_same_language(&room_state.chat.target_language, &response.language) {
+                // The room is read in another language since the translation was requested
+                continue;
+            }
+
+            match translation {
+                Ok(translation) => {
+                    if let Some(history) = &mut room_state.history
+                        && let Err(e) = history.append_translation(
+                            response.message_id,
+                            &translation,
+                            &response.language,
+                        )
+                    {
+                        tracing::warn!("Failed to save translation to history: {}", e);
+                    }
+                    room_state
+                        .chat
+                        .update_translation(response.message_id, translation);
+                }
+                Err(error) => room_state.chat.fail_translation(response.message_id, error),
+            }
         }
 
-        // Request translation for messages that need it and haven't been requested yet
-        // Only if AI is not disabled
+        // Request translation for messages of the shown room that need it and haven't been
+        // requested yet. Only if AI is not disabled
         if !config.disable_ai {
-            for message in &self.chat.messages {
-                if message.translation.is_none()
-                    && !self.translation_requests_sent.contains(&message.id)
+            let room_state = &self.rooms[self.active_room];
+            let target_language = &room_state.chat.target_language;
+            for (index, message) in room_state.chat.messages.iter().enumerate() {
+                if message.translation.is_some()
+                    || !message.needs_translation(target_language, self.public_key)
+                    || self.translation_requests_sent.contains(&message.id)
                 {
-                    let request = TranslationRequest {
-                        message_id: message.id,
-                        content: message.content.clone(),
-                        target_language: config.target_language.clone(),
-                    };
-                    if let Err(e) = translation_service.request_tx.send(request) {
-                        tracing::warn!("Failed to request translation: {}", e);
-                    } else {
-                        // Mark this message as having a translation request sent
-                        self.translation_requests_sent.insert(message.id);
-                    }
+                    continue;
                 }
+
+                let priority = if room_state.visible_messages.contains(&index) {
+                    Priority::Visible
+                } else {
+                    Priority::Background
+                };
+                let context = room_state
+                    .chat
+                    .context_before(message.id, config.translation_context);
+                translation_service.request_translation(
+                    message,
+                    target_language.clone(),
+                    priority,
+                    context,
+                );
+                // Mark this message as having a translation request sent
+                self.translation_requests_sent.insert(message.id);
             }
+
+            // Messages scrolled into view are translated first
+            translation_service.prioritize(&room_state.visible_message_ids());
         }
 
         // Handle network operations via background task
-        // Subscribe to chat group if we haven't already
-        if !self.subscribed && self.network_service.command_tx.is_some() {
-            if let Err(e) = self.network_service.subscribe(self.chat_group.clone()) {
-                tracing::warn!("Failed to subscribe to chat group: {}", e);
+        for room_state in &mut self.rooms {
+            // Subscribe to chat group if we haven't already
+            if !room_state.subscribed && self.network_service.command_tx.is_some() {
+                if let Err(e) = self
+                    .network_service
+                    .subscribe(room_state.chat_group.clone(), room_state.room.key.clone())
+                {
+                    tracing::warn!("Failed to subscribe to chat group: {}", e);
+                }
+                // Mark as subscription attempted to prevent spamming
+                room_state.subscribed = true;
             }
-            // Mark as subscription attempted to prevent spamming
-            self.subscribed = true;
-        }
 
-        // Send pending outgoing messages via background task
-        for content in self.pending_outgoing_messages.drain(..) {
-            let network_message = NetworkMessage::new(content, config.username.clone());
+            // Translate outgoing messages for peers who can't do it themselves
+            for outgoing in &mut room_state.pending_outgoing_messages {
+                if outgoing.requested {
+                    continue;
+                }
+                for language in &outgoing.awaiting {
+                    translation_service.queue(TranslationRequest {
+                        message_id: outgoing.message_id,
+                        content: outgoing.content.clone(),
+                        target_language: language.clone(),
+                        priority: Priority::Outgoing,
+                        context: room_state
+                            .chat
+                            .context_before(outgoing.message_id, config.translation_context),
+                    });
+                }
+                outgoing.requested = true;
+            }
 
-            if let Err(e) = self.network_service.send_message(network_message) {
-                tracing::warn!("Failed to queue network message: {}", e);
+            // Send pending outgoing messages via background task
+            for outgoing in room_state.take_ready_outgoing_messages() {
+                if !outgoing.awaiting.is_empty() {
+                    tracing::warn!(
+                        "Sending message {} without translations into {:?}",
+                        outgoing.message_id,
+                        outgoing.awaiting
+                    );
+                }
+
+                let mut network_message =
+                    NetworkMessage::new(outgoing.content, config.username.clone());
+                network_message.translations = outgoing.translations;
+
+                if let Err(e) = self
+                    .network_service
+                    .send_message(room_state.chat_group.clone(), network_message)
+                {
+                    tracing::warn!("Failed to queue network message: {}", e);
+                }
             }
         }
 
         // Process incoming network events
         while let Ok(Some(event)) = self.network_service.try_receive_event() {
             match event {
-                NetworkEvent::MessageReceived(network_message) => {
-                    // Add received message to chat
-                    if let Err(e) = self
-                        .chat
-                        .add_message(network_message.content, network_message.sender_id)
-                    {
-                        tracing::warn!("Failed to add received message: {}", e);
-                    } else {
-                        // Auto-scroll to bottom when new message is received
-                        self.scroll_to_bottom();
+                NetworkEvent::MessageReceived(chat_group, network_message) => {
+                    // Add received message to the chat of its room, which may be in the background
+                    if let Some(index) = self.room_index(&chat_group) {
+                        let is_active = index == self.active_room;
+                        self.rooms[index].receive_message(*network_message, is_active);
+                    }
+                }
+                NetworkEvent::PeerJoined(group, announcement) => {
+                    if let Some(index) = self.room_index(&group) {
+                        self.rooms[index].member_joined(*announcement);
+                    }
+                }
+                NetworkEvent::PeerAnnounced(group, announcement) => {
+                    if let Some(index) = self.room_index(&group) {
+                        self.rooms[index].member_announced(*announcement);
+                    }
+                }
+                NetworkEvent::PeerLeft(group, public_key) => {
+                    if let Some(index) = self.room_index(&group) {
+                        self.rooms[index].member_left(&public_key);
                     }
                 }
                 NetworkEvent::Subscribed(group) => {
                     tracing::info!("Successfully subscribed to chat group: {:?}", group);
-                    self.connection_status = ConnectionStatus::Connected;
+                    if let Some(index) = self.room_index(&group) {
+                        self.rooms[index].connection_status = ConnectionStatus::Connected;
+                    }
                 }
                 NetworkEvent::Error(error) => {
                     tracing::warn!("Network error: {:?}", error);
-                    // Reset subscription state on connection-related errors
-                    match error {
-                        NetworkError::SubscriptionLost | NetworkError::ChannelClosed => {
-                            self.subscribed = false;
-                            self.connection_status = ConnectionStatus::Disconnected;
+                    // Without a network no room can connect
+                    for room_state in &mut self.rooms {
+                        if let Some(reason) = handle_room_error(room_state, error.clone()) {
+                            self.status_message = reason;
                         }
-                        NetworkError::NetworkCreationFailed(ref msg)
-                        | NetworkError::SubscriptionFailed(ref msg) => {
-                            self.subscribed = false;
-                            self.connection_status = ConnectionStatus::Error(msg.clone());
-                        }
-                        NetworkError::SendFailed(_) | NetworkError::SerializationFailed(_) => {
-                            // Don't reset subscription for temporary send/serialization failures
-                            // Keep current connection status
+                    }
+                }
+                NetworkEvent::RoomError(group, error) => {
+                    tracing::warn!("Network error in {:?}: {:?}", group, error);
+                    if let Some(index) = self.room_index(&group) {
+                        let room_state = &mut self.rooms[index];
+                        if let Some(reason) = handle_room_error(room_state, error) {
+                            self.status_message =
+                                format!("{}: {}", room_state.display_name(), reason);
                         }
                     }
                 }
@@ -268,9 +694,44 @@ impl State for ChatState {
     }
 }
 
-fn render_messages_pane(f: &mut Frame, chat_state: &mut ChatState, area: Rect) {
+/// Update the connection status of a room after an error. Errors which leave the connection
+/// as it is are returned as a reason to show in the status.
+fn handle_room_error(room_state: &mut RoomState, error: NetworkError) -> Option<String> {
+    // Reset subscription state on connection-related errors
+    match error {
+        NetworkError::SubscriptionLost | NetworkError::ChannelClosed => {
+            room_state.subscribed = false;
+            room_state.connection_status = ConnectionStatus::Disconnected;
+            None
+        }
+        NetworkError::NetworkCreationFailed(msg) | NetworkError::SubscriptionFailed(msg) => {
+            room_state.subscribed = false;
+            room_state.connection_status = ConnectionStatus::Error(msg);
+            None
+        }
+        // Don't reset subscription for temporary send/serialization failures or single invalid
+        // operations from peers, which are dropped
+        NetworkError::SendFailed(reason) => Some(format!("Failed to send: {}", reason)),
+        NetworkError::SerializationFailed(reason) => {
+            Some(format!("Failed to encode message: {}", reason))
+        }
+        NetworkError::InvalidOperation(reason) => {
+            Some(format!("Dropped an invalid message: {}", reason))
+        }
+        NetworkError::InvalidSignature(reason) => Some(format!(
+            "Dropped a message with an invalid signature: {}",
+            reason
+        )),
+        NetworkError::DecryptionFailed(reason) => Some(format!(
+            "Dropped a message which couldn't be decrypted: {}",
+            reason
+        )),
+    }
+}
+
+fn render_messages_pane(f: &mut Frame, room_state: &mut RoomState, area: Rect) {
     // Check if we need to show error details
-    let has_error = matches!(chat_state.connection_status, ConnectionStatus::Error(_));
+    let has_error = matches!(room_state.connection_status, ConnectionStatus::Error(_));
 
     let constraints = if has_error {
         vec![Constraint::Min(0), Constraint::Length(2)]
@@ -283,7 +744,7 @@ fn render_messages_pane(f: &mut Frame, chat_state: &mut ChatState, area: Rect) {
         .constraints(constraints)
         .split(area);
 
-    let connection_indicator = match &chat_state.connection_status {
+    let connection_indicator = match &room_state.connection_status {
         ConnectionStatus::Connecting => "Connecting...",
         ConnectionStatus::Connected => "Connected",
         ConnectionStatus::Disconnected => "Disconnected",
@@ -292,21 +753,22 @@ fn render_messages_pane(f: &mut Frame, chat_state: &mut ChatState, area: Rect) {
 
     let title = format!(
         "Messages - {} [{}]",
-        chat_state.room.name, connection_indicator
+        room_state.display_name(),
+        connection_indicator
     );
 
     render_with_scroll_state(
         f,
-        chat_state,
+        room_state,
         chunks[0],
         title,
-        |msg| msg.display_original(),
+        |msg| (msg.display_original(), Style::default()),
         ScrollType::Messages,
     );
 
     // Show error details if there's an error
     if has_error {
-        if let ConnectionStatus::Error(ref error_msg) = chat_state.connection_status {
+        if let ConnectionStatus::Error(ref error_msg) = room_state.connection_status {
             let error_widget = Paragraph::new(error_msg.as_str())
                 .style(Style::default().fg(Color::Red))
                 .block(
@@ -327,24 +789,32 @@ enum ScrollType {
 
 fn render_with_scroll_state<F>(
     f: &mut Frame,
-    chat_state: &mut ChatState,
+    room_state: &mut RoomState,
     area: Rect,
     title: String,
     content_extractor: F,
     scroll_type: ScrollType,
 ) where
-    F: Fn(&crate::entities::chat::Message) -> String,
+    F: Fn(&crate::entities::chat::Message) -> (String, Style),
 {
-    // Extract the data we need before borrowing the scroll state
-    let content: Vec<String> = chat_state
+    // Extract the data we need before borrowing the scroll state, along with the index of the
+    // message each line belongs to
+    let selected_style = Style::default().bg(Color::DarkGray);
+    let (message_indices, content): (Vec<usize>, Vec<(String, Style)>) = room_state
         .chat
         .messages
         .iter()
-        .flat_map(|msg| {
-            let text = content_extractor(msg);
+        .enumerate()
+        .flat_map(|(index, msg)| {
+            let (text, mut style) = content_extractor(msg);
+            if room_state.selected_message == Some(index) {
+                style = style.patch(selected_style);
+            }
             wrap_text(&text, area.width.saturating_sub(4) as usize)
+                .into_iter()
+                .map(move |line| (index, (line, style)))
         })
-        .collect();
+        .unzip();
 
     let content_height = content.len() as u16;
     let content_size = Size::new(area.width.saturating_sub(2), content_height.max(1));
@@ -352,9 +822,9 @@ fn render_with_scroll_state<F>(
     let mut scroll_view = ScrollView::new(content_size);
 
     // Render each line as a separate paragraph
-    for (i, line) in content.iter().enumerate() {
+    for (i, (line, style)) in content.iter().enumerate() {
         let line_area = Rect::new(0, i as u16, area.width.saturating_sub(2), 1);
-        scroll_view.render_widget(Paragraph::new(line.as_str()), line_area);
+        scroll_view.render_widget(Paragraph::new(line.as_str()).style(*style), line_area);
     }
 
     // Render with border
@@ -363,22 +833,146 @@ fn render_with_scroll_state<F>(
     block.render(area, f.buffer_mut());
 
     let scroll_state = match scroll_type {
-        ScrollType::Messages => &mut chat_state.messages_scroll_state,
-        ScrollType::Translations => &mut chat_state.translations_scroll_state,
+        ScrollType::Messages => &mut room_state.messages_scroll_state,
+        ScrollType::Translations => &mut room_state.translations_scroll_state,
     };
 
     scroll_view.render(inner_area, f.buffer_mut(), scroll_state);
+
+    // Remember which messages are on screen, so they are translated first
+    let first_line = scroll_state.offset().y as usize;
+    let last_line = (first_line + inner_area.height as usize).min(message_indices.len());
+    let visible = message_indices
+        .get(first_line..last_line)
+        .unwrap_or_default();
+    room_state.visible_messages = match (visible.first(), visible.last()) {
+        (Some(first), Some(last)) => *first..*last + 1,
+        _ => 0..0,
+    };
 }
 
 fn render_input_box(f: &mut Frame, chat_state: &ChatState, area: Rect) {
-    let input = Paragraph::new(chat_state.input.as_str())
+    let (text, title) = match chat_state.input_mode {
+        ChatInputMode::Message => (chat_state.input.as_str(), "Input"),
+        ChatInputMode::CreatingRoom => (
+            chat_state.room_input.as_str(),
+            "New room name (Enter: Create, Esc: Cancel)",
+        ),
+        ChatInputMode::JoiningRoom => (
+            chat_state.room_input.as_str(),
+            "Room ID to join (Enter: Join, Esc: Cancel)",
+        ),
+        ChatInputMode::PickingLanguage => (chat_state.input.as_str(), "Input"),
+    };
+
+    let input = Paragraph::new(text)
         .style(Style::default().fg(Color::Yellow))
-        .block(Block::default().borders(Borders::ALL).title("Input"))
+        .block(Block::default().borders(Borders::ALL).title(title))
         .wrap(Wrap { trim: false });
 
     f.render_widget(input, area);
 }
 
+fn render_status(f: &mut Frame, chat_state: &ChatState, area: Rect) {
+    let status = Paragraph::new(chat_state.status_message.as_str())
+        .style(Style::default().fg(Color::Green))
+        .block(Block::default().borders(Borders::ALL).title("Status"));
+
+    f.render_widget(status, area);
+}
+
+fn render_room_sidebar(f: &mut Frame, chat_state: &ChatState, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Min(0),
+            Constraint::Length(9),
+        ])
+        .split(area);
+
+    let selected_style = Style::default().fg(Color::Yellow).bg(Color::Blue);
+    let normal_style = Style::default().fg(Color::White);
+    let unread_style = Style::default().fg(Color::Cyan);
+
+    let items: Vec<ListItem> = chat_state
+        .rooms
+        .iter()
+        .enumerate()
+        .map(|(index, room_state)| {
+            let (label, style) = if index == chat_state.active_room {
+                (room_state.display_name().to_string(), selected_style)
+            } else if room_state.unread > 0 {
+                (
+                    format!("{} ({})", room_state.display_name(), room_state.unread),
+                    unread_style,
+                )
+            } else {
+                (room_state.display_name().to_string(), normal_style)
+            };
+            ListItem::new(Line::from(Span::styled(label, style)))
+        })
+        .collect();
+
+    let rooms = List::new(items).block(Block::default().borders(Borders::ALL).title("Rooms"));
+    f.render_widget(rooms, chunks[0]);
+
+    render_members(f, &chat_state.rooms[chat_state.active_room], chunks[1]);
+
+    let help = Paragraph::new(
+        "Tab: Next room\nCtrl+N: New room\nCtrl+O: Join room\nCtrl+W: Leave\nCtrl+L: Language\nShift+Up: Select\nCtrl+R: Retry",
+    )
+    .style(Style::default().fg(Color::Gray))
+    .block(Block::default().borders(Borders::ALL));
+    f.render_widget(help, chunks[2]);
+}
+
+fn render_members(f: &mut Frame, room_state: &RoomState, area: Rect) {
+    let members = room_state.sorted_members();
+    let items: Vec<ListItem> = if members.is_empty() {
+        vec![ListItem::new(Line::from(Span::styled(
+            "No one else here yet",
+            Style::default().fg(Color::Gray),
+        )))]
+    } else {
+        let now = SystemTime::now();
+        members
+            .into_iter()
+            .map(|member| {
+                let announcement = &member.announcement;
+                let (color, status) = match member.presence() {
+                    Presence::Online => (Color::Green, "online".to_string()),
+                    Presence::Idle => (
+                        Color::Yellow,
+                        format!("idle, active {}", format_time_ago(member.last_active, now)),
+                    ),
+                    Presence::Offline => (
+                        Color::Gray,
+                        format!("seen {}", format_time_ago(member.last_seen, now)),
+                    ),
+                };
+                ListItem::new(vec![
+                    Line::from(vec![
+                        Span::styled("● ", Style::default().fg(color)),
+                        Span::raw(format!(
+                            "{} [{}]",
+                            announcement.display_name,
+                            fingerprint(&announcement.public_key)
+                        )),
+                    ]),
+                    Line::from(Span::styled(
+                        format!("  {}, {}", announcement.language, status),
+                        Style::default().fg(Color::Gray),
+                    )),
+                ])
+            })
+            .collect()
+    };
+
+    let members = List::new(items).block(Block::default().borders(Borders::ALL).title("Members"));
+    f.render_widget(members, area);
+}
+
 fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
     if max_width == 0 {
         return vec![text.to_string()];
@@ -417,15 +1011,56 @@ fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
     }
 }
 
-fn render_translation_pane(f: &mut Frame, chat_state: &mut ChatState, area: Rect, config: &Config) {
-    let title = format!("Translations ({})", config.target_language);
+fn render_translation_pane(
+    f: &mut Frame,
+    room_state: &mut RoomState,
+    area: Rect,
+    config: &Config,
+    reader: Option<PublicKey>,
+) {
+    let target_language = room_state.chat.target_language.clone();
+    let title = format!("Translations ({})", target_language);
 
     render_with_scroll_state(
         f,
-        chat_state,
+        room_state,
         area,
         title,
-        |msg| msg.display_translation(),
+        |msg| {
+            let style = match msg.translation_state(&target_language, reader, !config.disable_ai) {
+                TranslationState::Failed(_) => Style::default().fg(Color::Red),
+                _ => Style::default(),
+            };
+            (
+                msg.display_translation(&target_language, reader, !config.disable_ai),
+                style,
+            )
+        },
         ScrollType::Translations,
     );
 }
+
+/// Render the list of languages to read the shown room in, centered over `area`.
+fn render_language_picker(f: &mut Frame, chat_state: &mut ChatState, area: Rect) {
+    let [popup_area] = Layout::horizontal([Constraint::Length(36)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup_area] = Layout::vertical([Constraint::Percentage(60)])
+        .flex(Flex::Center)
+        .areas(popup_area);
+
+    let items: Vec<ListItem> = supported_languages()
+        .into_iter()
+        .map(ListItem::new)
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Language (Enter: Select, Esc: Cancel)"),
+        )
+        .highlight_style(Style::default().fg(Color::Yellow).bg(Color::Blue));
+
+    f.render_widget(Clear, popup_area);
+    f.render_stateful_widget(list, popup_area, &mut chat_state.language_picker);
+}
@@ -8,15 +8,23 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, Paragraph},
 };
 
+use std::time::SystemTime;
+
 use crate::config::Config;
+use crate::llm::ModelStatus;
 use crate::room_manager::{Room, copy_to_clipboard};
+use crate::saved_rooms::{SavedRoom, SavedRooms};
 use crate::translation_service::TranslationService;
-use crate::tui::{AppState, State, chat_state::ChatState};
+use crate::tui::{
+    AppState, State, chat_state::ChatState, format_time_ago, render_model_status_bar,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MenuOption {
     CreateRoom,
     JoinRoom,
+    /// Index into the saved rooms
+    Saved(usize),
 }
 
 #[derive(Debug)]
@@ -24,8 +32,12 @@ pub struct MainMenuState {
     pub selected_option: MenuOption,
     pub room_name_input: String,
     pub room_id_input: String,
+    pub nickname_input: String,
     pub input_mode: InputMode,
     pub status_message: String,
+    /// Rooms created and joined before. `None` if they could not be loaded.
+    pub saved_rooms: Option<SavedRooms>,
+    pub model_status: Option<ModelStatus>,
 }
 
 #[derive(Debug, Clone)]
@@ -33,23 +45,55 @@ pub enum InputMode {
     Menu,
     CreatingRoom,
     JoiningRoom,
+    RenamingRoom,
+    /// Asking whether to forget the selected saved room
+    ConfirmingForget,
 }
 
-impl Default for MainMenuState {
-    fn default() -> Self {
+impl MainMenuState {
+    pub fn new(config: &Config) -> Self {
+        let saved_rooms = match config
+            .data_dir()
+            .and_then(|data_dir| SavedRooms::load(&data_dir))
+        {
+            Ok(saved_rooms) => Some(saved_rooms),
+            Err(e) => {
+                tracing::warn!("Failed to load saved rooms: {}", e);
+                None
+            }
+        };
+
         Self {
             selected_option: MenuOption::CreateRoom,
             room_name_input: String::new(),
             room_id_input: String::new(),
+            nickname_input: String::new(),
             input_mode: InputMode::Menu,
             status_message: String::new(),
+            saved_rooms,
+            model_status: None,
         }
     }
-}
 
-impl MainMenuState {
-    pub fn new() -> Self {
-        Self::default()
+    fn saved_room_list(&self) -> &[SavedRoom] {
+        self.saved_rooms
+            .as_ref()
+            .map(|saved_rooms| saved_rooms.rooms())
+            .unwrap_or_default()
+    }
+
+    /// All selectable options in menu order
+    fn options(&self) -> Vec<MenuOption> {
+        let mut options = vec![MenuOption::CreateRoom, MenuOption::JoinRoom];
+        options.extend((0..self.saved_room_list().len()).map(MenuOption::Saved));
+        options
+    }
+
+    fn selected_saved_room(&self) -> Option<&SavedRoom> {
+        match self.selected_option {
+            MenuOption::Saved(index) => self.saved_room_list().get(index),
+            _ => None,
+        }
     }
 }
 
@@ -58,19 +102,22 @@ impl State for MainMenuState {
         &mut self,
         key: KeyCode,
         modifiers: KeyModifiers,
-        _config: &Config,
+        config: &Config,
     ) -> Result<Option<AppState>> {
         match (key, modifiers) {
             (KeyCode::Char('q'), KeyModifiers::CONTROL) => Ok(Some(AppState::Quit)),
             _ => match self.input_mode {
-                InputMode::Menu => self.handle_menu_input(key, modifiers),
-                InputMode::CreatingRoom => self.handle_create_room_input(key, modifiers),
-                InputMode::JoiningRoom => self.handle_join_room_input(key, modifiers),
+                InputMode::Menu => self.handle_menu_input(key, modifiers, config),
+                InputMode::CreatingRoom => self.handle_create_room_input(key, modifiers, config),
+                InputMode::JoiningRoom => self.handle_join_room_input(key, modifiers, config),
+                InputMode::RenamingRoom => self.handle_rename_room_input(key, modifiers),
+                InputMode::ConfirmingForget => self.handle_confirm_forget_input(key, modifiers),
             },
         }
     }
 
     fn render(&mut self, f: &mut Frame, _config: &Config) {
+        let area = render_model_status_bar(f, self.model_status.as_ref());
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -78,7 +125,7 @@ impl State for MainMenuState {
                 Constraint::Min(0),
                 Constraint::Length(3),
             ])
-            .split(f.area());
+            .split(area);
 
         // Title
         let title = Paragraph::new("Public Universal Friend")
@@ -119,13 +166,22 @@ impl State for MainMenuState {
             InputMode::Menu => self.render_menu(f, content_area),
             InputMode::CreatingRoom => self.render_create_room(f, content_area),
             InputMode::JoiningRoom => self.render_join_room(f, content_area),
+            InputMode::RenamingRoom => self.render_rename_room(f, content_area),
+            InputMode::ConfirmingForget => self.render_confirm_forget(f, content_area),
         }
 
         // Help text
         let help_text = match self.input_mode {
-            InputMode::Menu => "↑/↓ or j/k: Navigate, Enter: Select, Ctrl+Q: Quit",
+            InputMode::Menu => match self.selected_option {
+                MenuOption::Saved(_) => {
+                    "↑/↓ or j/k: Navigate, Enter: Rejoin, r: Rename, d: Forget, Ctrl+Q: Quit"
+                }
+                _ => "↑/↓ or j/k: Navigate, Enter: Select, Ctrl+Q: Quit",
+            },
             InputMode::CreatingRoom => "Type room name, Enter: Create, Esc: Back",
             InputMode::JoiningRoom => "Type room ID, Enter: Join, Esc: Back",
+            InputMode::RenamingRoom => "Type nickname, Enter: Save, Esc: Back",
+            InputMode::ConfirmingForget => "y/Enter: Forget, n/Esc: Keep",
         };
         let help = Paragraph::new(help_text)
             .style(Style::default().fg(Color::Gray))
@@ -134,7 +190,9 @@ impl State for MainMenuState {
         f.render_widget(help, chunks[2]);
     }
 
-    fn update(&mut self, _translation_service: &mut TranslationService, _config: &Config) {}
+    fn update(&mut self, translation_service: &mut TranslationService, _config: &Config) {
+        self.model_status = translation_service.model_status();
+    }
 }
 
 impl MainMenuState {
@@ -142,26 +200,46 @@ impl MainMenuState {
         &mut self,
         key: KeyCode,
         _modifiers: KeyModifiers,
+        config: &Config,
     ) -> Result<Option<AppState>> {
+        let options = self.options();
+        let selected = options
+            .iter()
+            .position(|option| *option == self.selected_option)
+            .unwrap_or(0);
+
         match key {
             KeyCode::Up | KeyCode::Char('k') => {
-                self.selected_option = MenuOption::CreateRoom;
+                self.selected_option = options[selected.saturating_sub(1)];
                 Ok(None)
             }
             KeyCode::Down | KeyCode::Char('j') => {
-                self.selected_option = MenuOption::JoinRoom;
+                self.selected_option = options[(selected + 1).min(options.len() - 1)];
                 Ok(None)
             }
-            KeyCode::Enter => {
-                match self.selected_option {
-                    MenuOption::CreateRoom => {
-                        self.input_mode = InputMode::CreatingRoom;
-                        self.room_name_input.clear();
-                    }
-                    MenuOption::JoinRoom => {
-                        self.input_mode = InputMode::JoiningRoom;
-                        self.room_id_input.clear();
-                    }
+            KeyCode::Enter => match self.selected_option {
+                MenuOption::CreateRoom => {
+                    self.input_mode = InputMode::CreatingRoom;
+                    self.room_name_input.clear();
+                    Ok(None)
+                }
+                MenuOption::JoinRoom => {
+                    self.input_mode = InputMode::JoiningRoom;
+                    self.room_id_input.clear();
+                    Ok(None)
+                }
+                MenuOption::Saved(_) => self.rejoin_selected_room(config),
+            },
+            KeyCode::Char('r') => {
+                if let Some(saved) = self.selected_saved_room() {
+                    self.nickname_input = saved.nickname.clone().unwrap_or_default();
+                    self.input_mode = InputMode::RenamingRoom;
+                }
+                Ok(None)
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if self.selected_saved_room().is_some() {
+                    self.input_mode = InputMode::ConfirmingForget;
                 }
                 Ok(None)
             }
@@ -169,10 +247,112 @@ impl MainMenuState {
         }
     }
 
+    fn rejoin_selected_room(&mut self, config: &Config) -> Result<Option<AppState>> {
+        let Some(saved) = self.selected_saved_room() else {
+            return Ok(None);
+        };
+
+        match Room::from_identifier(saved.identifier.clone()) {
+            Ok(room) => {
+                tracing::info!("Rejoining room: {}", room.identifier);
+                Ok(Some(AppState::Chat(Box::new(ChatState::with_room(
+                    room, config,
+                )))))
+            }
+            Err(e) => {
+                self.status_message = format!("Invalid saved room: {}", e);
+                tracing::warn!("Failed to parse saved room ID: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn forget_selected_room(&mut self) {
+        let Some(identifier) = self
+            .selected_saved_room()
+            .map(|saved| saved.identifier.clone())
+        else {
+            return;
+        };
+        let Some(saved_rooms) = &mut self.saved_rooms else {
+            return;
+        };
+
+        match saved_rooms.forget(&identifier) {
+            Ok(()) => {
+                self.status_message = "Room forgotten. Its chat history is kept.".to_string();
+                // Keep the selection in place, moving up if the last room was removed
+                self.selected_option = match self.saved_room_list().len() {
+                    0 => MenuOption::JoinRoom,
+                    len => match self.selected_option {
+                        MenuOption::Saved(index) => MenuOption::Saved(index.min(len - 1)),
+                        option => option,
+                    },
+                };
+            }
+            Err(e) => {
+                self.status_message = format!("Failed to forget room: {}", e);
+                tracing::warn!("Failed to forget room: {}", e);
+            }
+        }
+    }
+
+    fn handle_confirm_forget_input(
+        &mut self,
+        key: KeyCode,
+        _modifiers: KeyModifiers,
+    ) -> Result<Option<AppState>> {
+        match key {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                self.forget_selected_room();
+                self.input_mode = InputMode::Menu;
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                self.input_mode = InputMode::Menu;
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn handle_rename_room_input(
+        &mut self,
+        key: KeyCode,
+        _modifiers: KeyModifiers,
+    ) -> Result<Option<AppState>> {
+        match key {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Menu;
+            }
+            KeyCode::Char(c) => {
+                self.nickname_input.push(c);
+            }
+            KeyCode::Backspace => {
+                self.nickname_input.pop();
+            }
+            KeyCode::Enter => {
+                if let Some(identifier) = self
+                    .selected_saved_room()
+                    .map(|saved| saved.identifier.clone())
+                    && let Some(saved_rooms) = &mut self.saved_rooms
+                    && let Err(e) =
+                        saved_rooms.rename(&identifier, Some(self.nickname_input.clone()))
+                {
+                    self.status_message = format!("Failed to rename room: {}", e);
+                    tracing::warn!("Failed to rename room: {}", e);
+                }
+                self.input_mode = InputMode::Menu;
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
     fn handle_create_room_input(
         &mut self,
         key: KeyCode,
         _modifiers: KeyModifiers,
+        config: &Config,
     ) -> Result<Option<AppState>> {
         match key {
             KeyCode::Esc => {
@@ -211,7 +391,9 @@ impl MainMenuState {
                     }
 
                     // Transition to chat with room context
-                    Ok(Some(AppState::Chat(ChatState::with_room(room))))
+                    Ok(Some(AppState::Chat(Box::new(ChatState::with_room(
+                        room, config,
+                    )))))
                 } else {
                     Ok(None)
                 }
@@ -224,6 +406,7 @@ impl MainMenuState {
         &mut self,
         key: KeyCode,
         _modifiers: KeyModifiers,
+        config: &Config,
     ) -> Result<Option<AppState>> {
         match key {
             KeyCode::Esc => {
@@ -247,7 +430,9 @@ impl MainMenuState {
                             tracing::info!("Joining room: {}", room.identifier);
 
                             // Transition to chat with room context
-                            Ok(Some(AppState::Chat(ChatState::with_room(room))))
+                            Ok(Some(AppState::Chat(Box::new(ChatState::with_room(
+                                room, config,
+                            )))))
                         }
                         Err(e) => {
                             self.status_message = format!("Invalid room ID: {}", e);
@@ -264,38 +449,71 @@ impl MainMenuState {
     }
 
     fn render_menu(&self, f: &mut Frame, area: ratatui::layout::Rect) {
-        let _items = vec![
-            ListItem::new(Line::from(Span::raw("Create New Room"))),
-            ListItem::new(Line::from(Span::raw("Join Existing Room"))),
-        ];
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(4), Constraint::Min(0)])
+            .split(area.inner(Margin::new(2, 1)));
 
         let selected_style = Style::default().fg(Color::Yellow).bg(Color::Blue);
         let normal_style = Style::default().fg(Color::White);
+        let style_for = |option: MenuOption| {
+            if self.selected_option == option {
+                selected_style
+            } else {
+                normal_style
+            }
+        };
 
         // Manually highlight the selected item
         let styled_items: Vec<ListItem> = vec![
             ListItem::new(Line::from(Span::styled(
                 "Create New Room",
-                if matches!(self.selected_option, MenuOption::CreateRoom) {
-                    selected_style
-                } else {
-                    normal_style
-                },
+                style_for(MenuOption::CreateRoom),
             ))),
             ListItem::new(Line::from(Span::styled(
                 "Join Existing Room",
-                if matches!(self.selected_option, MenuOption::JoinRoom) {
-                    selected_style
-                } else {
-                    normal_style
-                },
+                style_for(MenuOption::JoinRoom),
             ))),
         ];
 
         let menu_list = List::new(styled_items)
             .block(Block::default().borders(Borders::ALL).title("Main Menu"));
 
-        f.render_widget(menu_list, area.inner(Margin::new(2, 1)));
+        f.render_widget(menu_list, chunks[0]);
+
+        let now = SystemTime::now();
+        let saved_items: Vec<ListItem> = self
+            .saved_room_list()
+            .iter()
+            .enumerate()
+            .map(|(index, saved)| {
+                let label = match &saved.nickname {
+                    Some(nickname) => format!("{} ({})", nickname, saved.name),
+                    None => saved.name.clone(),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(label, style_for(MenuOption::Saved(index))),
+                    Span::styled(
+                        format!("  {}", format_time_ago(saved.last_visited, now)),
+                        Style::default().fg(Color::Gray),
+                    ),
+                ]))
+            })
+            .collect();
+
+        let saved_list = if saved_items.is_empty() {
+            List::new(vec![ListItem::new(Span::styled(
+                "Rooms you create or join show up here",
+                Style::default().fg(Color::Gray),
+            ))])
+        } else {
+            List::new(saved_items)
+        };
+
+        f.render_widget(
+            saved_list.block(Block::default().borders(Borders::ALL).title("Saved Rooms")),
+            chunks[1],
+        );
     }
 
     fn render_create_room(&self, f: &mut Frame, area: ratatui::layout::Rect) {
@@ -330,11 +548,48 @@ impl MainMenuState {
         f.render_widget(input, chunks[0]);
 
         let instructions = Paragraph::new(
-            "Paste the room ID that was shared with you.\nRoom IDs look like: hash-uuid-room-name",
+            "Paste the room ID that was shared with you.\nRoom IDs look like: hash-uuid-key-room-name",
         )
         .style(Style::default().fg(Color::Gray))
         .block(Block::default().borders(Borders::ALL).title("Instructions"));
 
         f.render_widget(instructions, chunks[1]);
     }
+
+    fn render_rename_room(&self, f: &mut Frame, area: ratatui::layout::Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(area);
+
+        let input = Paragraph::new(self.nickname_input.as_str())
+            .style(Style::default().fg(Color::Yellow))
+            .block(Block::default().borders(Borders::ALL).title("Nickname"));
+
+        f.render_widget(input, chunks[0]);
+
+        let instructions = Paragraph::new(
+            "Enter a nickname for this room. It is only shown to you.\nLeave it empty to use the room name.",
+        )
+        .style(Style::default().fg(Color::Gray))
+        .block(Block::default().borders(Borders::ALL).title("Instructions"));
+
+        f.render_widget(instructions, chunks[1]);
+    }
+
+    fn render_confirm_forget(&self, f: &mut Frame, area: ratatui::layout::Rect) {
+        let name = self
+            .selected_saved_room()
+            .map(|saved| saved.nickname.as_deref().unwrap_or(&saved.name))
+            .unwrap_or_default();
+
+        let question = Paragraph::new(format!(
+            "Forget the room \"{}\"?\nIts chat history is kept, but you need the room ID to join it again.",
+            name
+        ))
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default().borders(Borders::ALL).title("Forget Room"));
+
+        f.render_widget(question, area);
+    }
 }
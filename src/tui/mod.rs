@@ -1,13 +1,21 @@
 use anyhow::Result;
 use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
-use ratatui::{Frame, Terminal, backend::Backend};
-use std::time::Duration;
+use ratatui::{
+    Frame, Terminal,
+    backend::Backend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style},
+    widgets::Paragraph,
+};
+use std::time::{Duration, SystemTime};
 
 use crate::config::Config;
+use crate::llm::ModelStatus;
 use crate::translation_service::TranslationService;
 
 pub mod chat_state;
 pub mod main_menu_state;
+pub mod room_state;
 
 use chat_state::ChatState;
 use main_menu_state::MainMenuState;
@@ -15,16 +23,10 @@ use main_menu_state::MainMenuState;
 #[derive(Debug)]
 pub enum AppState {
     MainMenu(MainMenuState),
-    Chat(ChatState),
+    Chat(Box<ChatState>),
     Quit,
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::MainMenu(MainMenuState::new())
-    }
-}
-
 pub trait State {
     fn handle_key_event(
         &mut self,
@@ -45,8 +47,8 @@ pub struct TuiApp {
 impl TuiApp {
     pub fn new(config: Config) -> Self {
         Self {
-            state: AppState::default(),
-            translation_service: TranslationService::new(),
+            state: AppState::MainMenu(MainMenuState::new(&config)),
+            translation_service: TranslationService::new(&config),
             config,
         }
     }
@@ -111,3 +113,75 @@ impl TuiApp {
         Ok(())
     }
 }
+
+/// Describe how long ago something happened, e.g. "3h ago"
+fn format_time_ago(time: SystemTime, now: SystemTime) -> String {
+    let seconds = now
+        .duration_since(time)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Render a one-line bar with the progress of loading the translation model at the bottom of
+/// the screen, until the model is ready. Returns the area left for the rest of the screen.
+fn render_model_status_bar(f: &mut Frame, status: Option<&ModelStatus>) -> Rect {
+    let (text, color) = match status {
+        None | Some(ModelStatus::Ready) => return f.area(),
+        Some(ModelStatus::Verifying) => (
+            "Translation model: verifying checksum...".to_string(),
+            Color::Yellow,
+        ),
+        Some(ModelStatus::Downloading {
+            source,
+            downloaded,
+            total,
+        }) => (
+            format!(
+                "Translation model: downloading {} {}% ({} of {})",
+                source,
+                (downloaded * 100).checked_div(*total).unwrap_or(0),
+                format_bytes(*downloaded),
+                format_bytes(*total)
+            ),
+            Color::Yellow,
+        ),
+        Some(ModelStatus::Loading(progress)) => (
+            format!("Translation model: loading {}%", (progress * 100.0) as u32),
+            Color::Yellow,
+        ),
+        Some(ModelStatus::Warming) => (
+            "Translation model: warming up...".to_string(),
+            Color::Yellow,
+        ),
+        Some(ModelStatus::Failed(error)) => {
+            (format!("Translation model failed: {}", error), Color::Red)
+        }
+    };
+
+    let [area, bar_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(f.area());
+    f.render_widget(
+        Paragraph::new(text).style(Style::default().fg(color)),
+        bar_area,
+    );
+    area
+}
+
+/// Describe a size in bytes, e.g. "1.2 GB"
+fn format_bytes(bytes: u64) -> String {
+    const MB: u64 = 1_000_000;
+    const GB: u64 = 1_000_000_000;
+
+    if bytes >= GB {
+        format!("{:.1} GB", bytes as f64 / GB as f64)
+    } else {
+        format!("{} MB", bytes / MB)
+    }
+}
@@ -0,0 +1,476 @@
+use anyhow::Result;
+use p2panda_core::PublicKey;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::time::{Duration, Instant, SystemTime};
+use tui_scrollview::ScrollViewState;
+
+use crate::config::Config;
+use crate::entities::chat::Chat;
+use crate::history::ChatHistory;
+use crate::language::is_same_language;
+use crate::p2p::{ChatGroup, NetworkMessage, PeerAnnouncement};
+use crate::room_manager::Room;
+use crate::saved_rooms::SavedRooms;
+
+/// How long a message waits for its outgoing translations before it is sent without the
+/// missing ones
+const OUTGOING_TRANSLATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Members who haven't sent a message for this long are shown as idle
+const IDLE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    Disconnected,
+    Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Presence {
+    Online,
+    Idle,
+    Offline,
+}
+
+/// A peer who announced themselves in a room
+#[derive(Debug)]
+pub struct Member {
+    pub announcement: PeerAnnouncement,
+    /// Whether the peer is still sending heartbeats
+    pub online: bool,
+    /// When we last heard from the peer
+    pub last_seen: SystemTime,
+    /// When the peer joined or last sent a message
+    pub last_active: SystemTime,
+}
+
+impl Member {
+    pub fn presence(&self) -> Presence {
+        if !self.online {
+            Presence::Offline
+        } else if self
+            .last_active
+            .elapsed()
+            .is_ok_and(|elapsed| elapsed >= IDLE_AFTER)
+        {
+            Presence::Idle
+        } else {
+            Presence::Online
+        }
+    }
+}
+
+/// A message of ours waiting to be sent, until the translations attached to it are done.
+#[derive(Debug)]
+pub struct OutgoingMessage {
+    /// ID of the message in our chat, which translation responses refer to
+    pub message_id: u64,
+    pub content: String,
+    pub translations: BTreeMap<String, String>,
+    /// Languages still being translated into
+    pub awaiting: HashSet<String>,
+    /// Whether the translations were requested from the translation service
+    pub requested: bool,
+    pub queued_at: Instant,
+}
+
+impl OutgoingMessage {
+    pub fn is_ready(&self) -> bool {
+        self.awaiting.is_empty() || self.queued_at.elapsed() >= OUTGOING_TRANSLATION_TIMEOUT
+    }
+}
+
+/// A joined room, which keeps receiving messages while another room is shown.
+#[derive(Debug)]
+pub struct RoomState {
+    pub chat: Chat,
+    pub room: Room,
+    /// Local name for the room from the saved rooms
+    pub nickname: Option<String>,
+    pub chat_group: ChatGroup,
+    pub pending_outgoing_messages: Vec<OutgoingMessage>,
+    pub subscribed: bool,
+    pub connection_status: ConnectionStatus,
+    /// Peers who announced themselves in this room, including those who left since
+    pub members: HashMap<PublicKey, Member>,
+    /// Messages received while the room was not shown
+    pub unread: usize,
+    pub messages_scroll_state: ScrollViewState,
+    pub translations_scroll_state: ScrollViewState,
+    /// Indices of the messages which were on screen when the room was last rendered
+    pub visible_messages: Range<usize>,
+    /// Index of the message selected to act on
+    pub selected_message: Option<usize>,
+    pub history: Option<ChatHistory>,
+}
+
+impl RoomState {
+    pub fn new(room: Room, config: &Config) -> Self {
+        let chat_group = room.to_chat_group();
+
+        // Reload scrollback for this room. History is best-effort: chatting still works without it.
+        let mut chat = Chat::new();
+        chat.target_language = config.target_language.clone();
+        let history = match config
+            .data_dir()
+            .and_then(|data_dir| ChatHistory::open(&data_dir, &room))
+        {
+            Ok(mut history) => match history.load() {
+                Ok(mut messages) => {
+                    // Translations stored while reading in another language are made again
+                    for message in &mut messages {
+                        if message
+                            .translation_language
+                            .as_ref()
+                            .is_some_and(|language| {
+                                !is_same_language(language, &chat.target_language)
+                            })
+                        {
+                            message.translation = None;
+                            message.translation_language = None;
+                        }
+                    }
+                    chat.messages = messages;
+                    Some(history)
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to load chat history: {}", e);
+                    None
+                }
+            },
+            Err(e) => {
+                tracing::warn!("Failed to open chat history: {}", e);
+                None
+            }
+        };
+
+        // Remember the room so it can be rejoined from the main menu
+        let nickname = match config
+            .data_dir()
+            .and_then(|data_dir| SavedRooms::load(&data_dir))
+        {
+            Ok(mut saved_rooms) => {
+                if let Err(e) = saved_rooms.record_visit(&room) {
+                    tracing::warn!("Failed to save room: {}", e);
+                }
+                saved_rooms
+                    .get(&room.identifier)
+                    .and_then(|saved| saved.nickname.clone())
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load saved rooms: {}", e);
+                None
+            }
+        };
+
+        let mut state = Self {
+            chat,
+            room,
+            nickname,
+            chat_group,
+            pending_outgoing_messages: Vec::new(),
+            subscribed: false,
+            connection_status: ConnectionStatus::Connecting,
+            members: HashMap::new(),
+            unread: 0,
+            messages_scroll_state: ScrollViewState::default(),
+            translations_scroll_state: ScrollViewState::default(),
+            visible_messages: 0..0,
+            selected_message: None,
+            history,
+        };
+        state.scroll_to_bottom();
+        state
+    }
+
+    /// Nickname if one was set, otherwise the room name
+    pub fn display_name(&self) -> &str {
+        self.nickname.as_deref().unwrap_or(&self.room.name)
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        // Auto-scroll to the bottom by setting scroll position to max
+        self.messages_scroll_state.scroll_to_bottom();
+        self.translations_scroll_state.scroll_to_bottom();
+    }
+
+    /// Read messages in another language. Translations into the previous language are cleared.
+    pub fn set_target_language(&mut self, language: String) {
+        tracing::info!(
+            "Reading {} in {} instead of {}",
+            self.room.identifier,
+            language,
+            self.chat.target_language
+        );
+        self.chat.set_target_language(language);
+    }
+
+    /// Select the message before the selected one, or the newest if none is selected.
+    pub fn select_previous_message(&mut self) {
+        self.selected_message = match self.selected_message {
+            Some(index) => Some(index.saturating_sub(1)),
+            None => self.chat.messages.len().checked_sub(1),
+        };
+    }
+
+    /// Select the message after the selected one. Moving past the newest message clears the
+    /// selection.
+    pub fn select_next_message(&mut self) {
+        self.selected_message = self
+            .selected_message
+            .map(|index| index + 1)
+            .filter(|index| *index < self.chat.messages.len());
+    }
+
+    /// Translate the selected message again if translating it failed, or all failed messages
+    /// if none is selected. Returns the IDs of the messages to translate again.
+    pub fn retry_failed_translations(&mut self) -> Vec<u64> {
+        let ids: Vec<u64> = match self.selected_message {
+            Some(index) => self
+                .chat
+                .messages
+                .get(index)
+                .map(|message| message.id)
+                .into_iter()
+                .collect(),
+            None => self
+                .chat
+                .messages
+                .iter()
+                .map(|message| message.id)
+                .collect(),
+        };
+        ids.into_iter()
+            .filter(|id| self.chat.retry_translation(*id))
+            .collect()
+    }
+
+    /// IDs of the messages which were on screen when the room was last rendered
+    pub fn visible_message_ids(&self) -> HashSet<u64> {
+        self.chat
+            .messages
+            .get(self.visible_messages.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|message| message.id)
+            .collect()
+    }
+
+    /// Add a peer who joined the room, or mark them online again if they left before.
+    pub fn member_joined(&mut self, announcement: PeerAnnouncement) {
+        let now = SystemTime::now();
+        self.members.insert(
+            announcement.public_key,
+            Member {
+                announcement,
+                online: true,
+                last_seen: now,
+                last_active: now,
+            },
+        );
+    }
+
+    /// Update a member from their periodic announcement.
+    pub fn member_announced(&mut self, announcement: PeerAnnouncement) {
+        match self.members.get_mut(&announcement.public_key) {
+            Some(member) => {
+                member.announcement = announcement;
+                member.online = true;
+                member.last_seen = SystemTime::now();
+            }
+            None => self.member_joined(announcement),
+        }
+    }
+
+    pub fn member_left(&mut self, public_key: &PublicKey) {
+        if let Some(member) = self.members.get_mut(public_key) {
+            member.online = false;
+        }
+    }
+
+    /// Members sorted by presence, then by display name
+    pub fn sorted_members(&self) -> Vec<&Member> {
+        let mut members: Vec<_> = self.members.values().collect();
+        members.sort_by(|a, b| {
+            a.presence().cmp(&b.presence()).then_with(|| {
+                a.announcement
+                    .display_name
+                    .cmp(&b.announcement.display_name)
+            })
+        });
+        members
+    }
+
+    /// Languages to attach translations for: the configured outgoing languages and those of
+    /// online members who don't translate themselves.
+    fn outgoing_languages(&self, config: &Config) -> Vec<String> {
+        let mut languages: Vec<String> = Vec::new();
+        let announced = self
+            .members
+            .values()
+            .filter(|member| member.online && !member.announcement.translates_locally)
+            .map(|member| &member.announcement.language);
+
+        for language in config.outgoing_translations.iter().chain(announced) {
+            if !languages
+                .iter()
+                .any(|known| is_same_language(known, language))
+            {
+                languages.push(language.clone());
+            }
+        }
+        languages
+    }
+
+    /// Add a message sent by us, store it and queue it for network broadcasting. The message
+    /// is held back until it is translated into the configured outgoing languages and those
+    /// of members who don't translate themselves, unless `translate` is false because no
+    /// model is ready to do so.
+    pub fn send_message(
+        &mut self,
+        content: String,
+        config: &Config,
+        public_key: Option<PublicKey>,
+        translate: bool,
+    ) -> Result<()> {
+        let outgoing_languages = self.outgoing_languages(config);
+        let message =
+            self.chat
+                .add_message(content.clone(), config.username.clone(), public_key)?;
+
+        if let Some(history) = &mut self.history
+            && let Err(e) = history.append_message(message)
+        {
+            tracing::warn!("Failed to save message to history: {}", e);
+        }
+
+        let awaiting = if !translate {
+            HashSet::new()
+        } else {
+            outgoing_languages
+                .into_iter()
+                .filter(|language| {
+                    message
+                        .source_language
+                        .as_ref()
+                        .is_none_or(|source| !is_same_language(source, language))
+                })
+                .collect()
+        };
+
+        self.pending_outgoing_messages.push(OutgoingMessage {
+            message_id: message.id,
+            content,
+            translations: BTreeMap::new(),
+            awaiting,
+            requested: false,
+            queued_at: Instant::now(),
+        });
+
+        // Auto-scroll to bottom when new message is added
+        self.scroll_to_bottom();
+
+        Ok(())
+    }
+
+    /// Attach a finished translation to a message waiting to be sent. A failed translation,
+    /// `None`, is left out so the message isn't held back for it. Returns false if no outgoing
+    /// message waits for it.
+    pub fn attach_outgoing_translation(
+        &mut self,
+        message_id: u64,
+        language: &str,
+        translation: Option<&str>,
+    ) -> bool {
+        let Some(outgoing) = self
+            .pending_outgoing_messages
+            .iter_mut()
+            .find(|outgoing| outgoing.message_id == message_id)
+        else {
+            return false;
+        };
+        if !outgoing.awaiting.remove(language) {
+            return false;
+        }
+
+        match translation {
+            Some(translation) => {
+                outgoing
+                    .translations
+                    .insert(language.to_string(), translation.to_string());
+            }
+            None => tracing::warn!(
+                "Sending message {} without its failed translation into {}",
+                message_id,
+                language
+            ),
+        }
+        true
+    }
+
+    /// Take the messages which are ready to be sent, keeping their order. A message still
+    /// being translated holds back those after it until it times out.
+    pub fn take_ready_outgoing_messages(&mut self) -> Vec<OutgoingMessage> {
+        let ready = self
+            .pending_outgoing_messages
+            .iter()
+            .take_while(|outgoing| outgoing.is_ready())
+            .count();
+        self.pending_outgoing_messages.drain(..ready).collect()
+    }
+
+    /// Add a message received from the network and store it, along with a translation the
+    /// sender attached for the language we read the room in. Messages in a room which is not
+    /// shown count as unread.
+    pub fn receive_message(&mut self, network_message: NetworkMessage, is_active: bool) {
+        let attached = network_message
+            .translations
+            .into_iter()
+            .find(|(language, _)| is_same_language(language, &self.chat.target_language));
+
+        if let Some(member) = network_message
+            .author
+            .and_then(|author| self.members.get_mut(&author))
+        {
+            member.last_active = SystemTime::now();
+        }
+
+        match self.chat.add_message(
+            network_message.content,
+            network_message.sender_id,
+            network_message.author,
+        ) {
+            Ok(message) => {
+                let message_id = message.id;
+                if let Some(history) = &mut self.history
+                    && let Err(e) = history.append_message(message)
+                {
+                    tracing::warn!("Failed to save message to history: {}", e);
+                }
+
+                if let Some((language, translation)) = attached {
+                    if let Some(history) = &mut self.history
+                        && let Err(e) =
+                            history.append_translation(message_id, &translation, &language)
+                    {
+                        tracing::warn!("Failed to save translation to history: {}", e);
+                    }
+                    self.chat.update_translation(message_id, translation);
+                }
+
+                if is_active {
+                    // Auto-scroll to bottom when new message is received
+                    self.scroll_to_bottom();
+                } else {
+                    self.unread += 1;
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to add received message: {}", e);
+            }
+        }
+    }
+}